@@ -0,0 +1,180 @@
+; Flag test: what the arithmetic leaves in VF, a tick per case that's right.
+; Run with the vip quirks.
+; Rebuild with: cargo run -- asm roms/tests/flags.asm roms/tests/flags.ch8
+    CLS
+    LD VC, 0
+    LD VD, 0
+
+    ; 8xy4 carries
+    LD VE, 0
+    LD V0, 0xFF
+    LD V1, 0x02
+    ADD V0, V1
+    SE V0, 0x01
+    JP add_carry
+    SNE VF, 1
+    LD VE, 1
+add_carry:
+    CALL mark
+
+    ; 8xy4 doesn't
+    LD VE, 0
+    LD V0, 0xFD
+    ADD V0, V1
+    SE V0, 0xFF
+    JP add_no_carry
+    SNE VF, 0
+    LD VE, 1
+add_no_carry:
+    CALL mark
+
+    ; 8xy5 doesn't borrow
+    LD VE, 0
+    LD V0, 0x05
+    LD V1, 0x02
+    SUB V0, V1
+    SE V0, 0x03
+    JP sub_no_borrow
+    SNE VF, 1
+    LD VE, 1
+sub_no_borrow:
+    CALL mark
+
+    ; 8xy5 borrows
+    LD VE, 0
+    LD V0, 0x01
+    SUB V0, V1
+    SE V0, 0xFF
+    JP sub_borrow
+    SNE VF, 0
+    LD VE, 1
+sub_borrow:
+    CALL mark
+
+    ; 8xy5 with equal operands doesn't borrow
+    LD VE, 0
+    LD V0, 0x02
+    SUB V0, V1
+    SE V0, 0x00
+    JP sub_equal
+    SNE VF, 1
+    LD VE, 1
+sub_equal:
+    CALL mark
+
+    ; 8xy7 doesn't borrow
+    LD VE, 0
+    LD V0, 0x02
+    LD V1, 0x05
+    SUBN V0, V1
+    SE V0, 0x03
+    JP subn_no_borrow
+    SNE VF, 1
+    LD VE, 1
+subn_no_borrow:
+    CALL mark
+
+    ; 8xy7 borrows
+    LD VE, 0
+    LD V0, 0x06
+    SUBN V0, V1
+    SE V0, 0xFF
+    JP subn_borrow
+    SNE VF, 0
+    LD VE, 1
+subn_borrow:
+    CALL mark
+
+    ; 8xy6 shifts out a 1
+    LD VE, 0
+    LD V0, 0x03
+    LD V1, 0x03
+    SHR V0, V1
+    SE V0, 0x01
+    JP shr_one
+    SNE VF, 1
+    LD VE, 1
+shr_one:
+    CALL mark
+
+    ; 8xy6 shifts out a 0
+    LD VE, 0
+    LD V0, 0x04
+    LD V1, 0x04
+    SHR V0, V1
+    SE V0, 0x02
+    JP shr_zero
+    SNE VF, 0
+    LD VE, 1
+shr_zero:
+    CALL mark
+
+    ; 8xyE shifts out a 1
+    LD VE, 0
+    LD V0, 0x81
+    LD V1, 0x81
+    SHL V0, V1
+    SE V0, 0x02
+    JP shl_one
+    SNE VF, 1
+    LD VE, 1
+shl_one:
+    CALL mark
+
+    ; 8xyE shifts out a 0
+    LD VE, 0
+    LD V0, 0x41
+    LD V1, 0x41
+    SHL V0, V1
+    SE V0, 0x82
+    JP shl_zero
+    SNE VF, 0
+    LD VE, 1
+shl_zero:
+    CALL mark
+
+    ; VF as the destination of 8xy4, the flag is written last
+    LD VE, 0
+    LD VF, 0xFF
+    LD V1, 0x02
+    ADD VF, V1
+    SNE VF, 1
+    LD VE, 1
+    CALL mark
+
+    ; VF as the destination of 8xy5
+    LD VE, 0
+    LD VF, 0x05
+    SUB VF, V1
+    SNE VF, 1
+    LD VE, 1
+    CALL mark
+
+done:
+    JP done
+
+; VE = 1 draws a tick at VC, VD, anything else a cross, then moves on to the next cell
+mark:
+    LD I, cross
+    SNE VE, 1
+    LD I, tick
+    DRW VC, VD, 5
+    ADD VC, 8
+    SE VC, 64
+    RET
+    LD VC, 0
+    ADD VD, 6
+    RET
+
+tick:
+    sprite .....#
+    sprite ....#.
+    sprite #..#..
+    sprite .##...
+    sprite ......
+cross:
+    sprite #...#
+    sprite .#.#.
+    sprite ..#..
+    sprite .#.#.
+    sprite #...#
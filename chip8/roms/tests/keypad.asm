@@ -0,0 +1,62 @@
+; Keypad test, needs someone at the keys: press and release A, then press 5 and let go.
+; A tick appears for each step and the key Fx0A returned is drawn underneath.
+; Run with the vip quirks.
+; Rebuild with: cargo run -- asm roms/tests/keypad.asm roms/tests/keypad.ch8
+    CLS
+    LD VC, 0
+    LD VD, 0
+
+    ; Fx0A waits for a key to be pressed and released
+    LD VE, 0
+    LD V0, K
+    SNE V0, 0xA
+    LD VE, 1
+    CALL mark
+    LD F, V0
+    LD V1, 0
+    LD V2, 8
+    DRW V1, V2, 5
+
+    ; Ex9E skips while 5 is held
+    LD V1, 5
+wait_press:
+    SKP V1
+    JP wait_press
+    LD VE, 1
+    CALL mark
+
+    ; ExA1 skips while 5 is up
+wait_release:
+    SKNP V1
+    JP wait_release
+    LD VE, 1
+    CALL mark
+
+done:
+    JP done
+
+; VE = 1 draws a tick at VC, VD, anything else a cross, then moves on to the next cell
+mark:
+    LD I, cross
+    SNE VE, 1
+    LD I, tick
+    DRW VC, VD, 5
+    ADD VC, 8
+    SE VC, 64
+    RET
+    LD VC, 0
+    ADD VD, 6
+    RET
+
+tick:
+    sprite .....#
+    sprite ....#.
+    sprite #..#..
+    sprite .##...
+    sprite ......
+cross:
+    sprite #...#
+    sprite .#.#.
+    sprite ..#..
+    sprite .#.#.
+    sprite #...#
@@ -0,0 +1,274 @@
+; Opcode test: one cell per instruction, a tick when it did what it should and a cross when not.
+; Run with the vip quirks. V0-VB are scratch, VC/VD is where the next cell goes and VE the result.
+; Rebuild with: cargo run -- asm roms/tests/opcodes.asm roms/tests/opcodes.ch8
+    CLS
+    LD VC, 0
+    LD VD, 0
+
+    ; 6xnn, 4xnn
+    LD VE, 0
+    LD V0, 0x42
+    SNE V0, 0x42
+    LD VE, 1
+    CALL mark
+
+    ; 3xnn
+    LD VE, 1
+    SE V0, 0x42
+    LD VE, 0
+    CALL mark
+
+    ; 5xy0
+    LD V1, 0x42
+    LD VE, 1
+    SE V0, V1
+    LD VE, 0
+    CALL mark
+
+    ; 9xy0
+    LD V1, 0x43
+    LD VE, 1
+    SNE V0, V1
+    LD VE, 0
+    CALL mark
+
+    ; 7xnn
+    LD VE, 0
+    LD V0, 0x10
+    ADD V0, 0x22
+    SNE V0, 0x32
+    LD VE, 1
+    CALL mark
+
+    ; 7xnn wraps around and leaves VF alone
+    LD VE, 0
+    LD VF, 0
+    LD V1, 0xF0
+    ADD V1, 0x20
+    SE V1, 0x10
+    JP wrap_done
+    SNE VF, 0
+    LD VE, 1
+wrap_done:
+    CALL mark
+
+    ; 8xy0
+    LD VE, 0
+    LD V1, V0
+    SNE V1, 0x32
+    LD VE, 1
+    CALL mark
+
+    ; 8xy1
+    LD VE, 0
+    LD V0, 0x0F
+    LD V1, 0xF0
+    OR V0, V1
+    SNE V0, 0xFF
+    LD VE, 1
+    CALL mark
+
+    ; 8xy2
+    LD VE, 0
+    LD V0, 0x3C
+    LD V1, 0x0F
+    AND V0, V1
+    SNE V0, 0x0C
+    LD VE, 1
+    CALL mark
+
+    ; 8xy3
+    LD VE, 0
+    LD V0, 0x3C
+    XOR V0, V1
+    SNE V0, 0x33
+    LD VE, 1
+    CALL mark
+
+    ; 8xy4
+    LD VE, 0
+    LD V0, 0x10
+    LD V1, 0x20
+    ADD V0, V1
+    SNE V0, 0x30
+    LD VE, 1
+    CALL mark
+
+    ; 8xy5
+    LD VE, 0
+    LD V1, 0x10
+    SUB V0, V1
+    SNE V0, 0x20
+    LD VE, 1
+    CALL mark
+
+    ; 8xy7
+    LD VE, 0
+    LD V0, 0x10
+    LD V1, 0x30
+    SUBN V0, V1
+    SNE V0, 0x20
+    LD VE, 1
+    CALL mark
+
+    ; 8xy6, both registers the same so it passes with either shift quirk
+    LD VE, 0
+    LD V0, 0x0A
+    LD V1, 0x0A
+    SHR V0, V1
+    SNE V0, 0x05
+    LD VE, 1
+    CALL mark
+
+    ; 8xyE
+    LD VE, 0
+    LD V0, 0x41
+    LD V1, 0x41
+    SHL V0, V1
+    SNE V0, 0x82
+    LD VE, 1
+    CALL mark
+
+    ; Annn, Fx1E, Fx65
+    LD VE, 0
+    LD I, data
+    LD V0, 1
+    ADD I, V0
+    LD V0, [I]
+    SNE V0, 0x22
+    LD VE, 1
+    CALL mark
+
+    ; Fx33
+    LD VE, 0
+    LD V0, 254
+    LD I, scratch
+    LD B, V0
+    LD I, scratch
+    LD V2, [I]
+    SE V0, 2
+    JP bcd_done
+    SE V1, 5
+    JP bcd_done
+    SNE V2, 4
+    LD VE, 1
+bcd_done:
+    CALL mark
+
+    ; Fx55
+    LD VE, 0
+    LD V0, 1
+    LD V1, 2
+    LD V2, 3
+    LD I, scratch
+    LD [I], V2
+    LD V0, 0
+    LD V2, 0
+    LD I, scratch
+    LD V2, [I]
+    SE V0, 1
+    JP store_done
+    SNE V2, 3
+    LD VE, 1
+store_done:
+    CALL mark
+
+    ; 2nnn, 00EE
+    LD VE, 0
+    LD V0, 0
+    CALL increment
+    SNE V0, 1
+    LD VE, 1
+    CALL mark
+
+    ; 1nnn
+    LD VE, 1
+    JP jumped
+    LD VE, 0
+jumped:
+    CALL mark
+
+    ; Bnnn, jumps to nnn + V0 on the vip
+    LD VE, 0
+    LD V0, 2
+    JP V0, table
+table:
+    JP table_done
+    LD VE, 1
+table_done:
+    CALL mark
+
+    ; Fx15, Fx07
+    LD VE, 0
+    LD V0, 0x20
+    LD DT, V0
+    LD V1, DT
+    SNE V1, 0x20
+    LD VE, 1
+    CALL mark
+
+    ; Cxnn, a zero mask always gives 0
+    LD VE, 0
+    LD V0, 0xFF
+    RND V0, 0
+    SNE V0, 0
+    LD VE, 1
+    CALL mark
+
+    ; Fx29, the first row of the A glyph
+    LD VE, 0
+    LD V0, 0xA
+    LD F, V0
+    LD V0, [I]
+    SNE V0, 0xF0
+    LD VE, 1
+    CALL mark
+
+    ; Dxyn, drawing the same sprite twice collides and leaves nothing behind
+    LD VE, 0
+    LD I, tick
+    LD V0, 56
+    LD V1, 26
+    DRW V0, V1, 5
+    DRW V0, V1, 5
+    SNE VF, 1
+    LD VE, 1
+    CALL mark
+
+done:
+    JP done
+
+increment:
+    ADD V0, 1
+    RET
+
+; VE = 1 draws a tick at VC, VD, anything else a cross, then moves on to the next cell
+mark:
+    LD I, cross
+    SNE VE, 1
+    LD I, tick
+    DRW VC, VD, 5
+    ADD VC, 8
+    SE VC, 64
+    RET
+    LD VC, 0
+    ADD VD, 6
+    RET
+
+tick:
+    sprite .....#
+    sprite ....#.
+    sprite #..#..
+    sprite .##...
+    sprite ......
+cross:
+    sprite #...#
+    sprite .#.#.
+    sprite ..#..
+    sprite .#.#.
+    sprite #...#
+
+data:
+    db 0x11, 0x22
+scratch:
+    db 0, 0, 0
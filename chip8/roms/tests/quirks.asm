@@ -0,0 +1,125 @@
+; Quirk test: a tick for each quirk that is on, in the order they appear in `Quirks`:
+; shift_uses_vy, load_store_increments_i, jump_uses_vx, clip_sprites, logic_resets_vf and
+; display_wait. Run it under each preset to check the preset's quirks took effect.
+; Rebuild with: cargo run -- asm roms/tests/quirks.asm roms/tests/quirks.ch8
+    JP start
+
+; BXnn reads x from the high nibble of the address, 2 here, so V2 picks the landing spot
+jump_table:
+    JP jump_done
+    LD VE, 1
+jump_done:
+    CALL mark
+    JP after_jump
+
+start:
+    CLS
+    LD VC, 0
+    LD VD, 8
+
+    ; shift_uses_vy
+    LD VE, 0
+    LD V0, 0x10
+    LD V1, 0x04
+    SHR V0, V1
+    SNE V0, 0x02
+    LD VE, 1
+    CALL mark
+
+    ; load_store_increments_i, a second store lands after the first one
+    LD VE, 0
+    LD V0, 0x11
+    LD I, scratch
+    LD [I], V0
+    LD V0, 0x22
+    LD [I], V0
+    LD I, scratch
+    LD V0, [I]
+    SNE V0, 0x11
+    LD VE, 1
+    CALL mark
+
+    ; jump_uses_vx
+    LD VE, 0
+    LD V0, 0
+    LD V2, 2
+    JP V0, jump_table
+after_jump:
+
+    ; clip_sprites, a 3 pixel row at x=62 either stops at the edge or wraps onto x=0
+    LD VE, 0
+    LD V0, 62
+    LD V1, 0
+    LD V2, 0
+    LD I, three
+    DRW V0, V1, 1
+    LD I, one
+    DRW V2, V1, 1
+    SNE VF, 0
+    LD VE, 1
+    DRW V2, V1, 1
+    LD I, three
+    DRW V0, V1, 1
+    CALL mark
+
+    ; logic_resets_vf
+    LD VE, 0
+    LD VF, 5
+    OR V0, V1
+    SNE VF, 0
+    LD VE, 1
+    CALL mark
+
+    ; display_wait, four draws take three frames or more if each waits for the vblank
+    LD VE, 0
+    LD V0, 10
+    LD DT, V0
+    LD I, blank
+    DRW V0, V0, 1
+    DRW V0, V0, 1
+    DRW V0, V0, 1
+    DRW V0, V0, 1
+    LD V1, DT
+    LD V2, 9
+    SUB V1, V2
+    SNE VF, 0
+    LD VE, 1
+    CALL mark
+
+done:
+    JP done
+
+; VE = 1 draws a tick at VC, VD, anything else a cross, then moves on to the next cell
+mark:
+    LD I, cross
+    SNE VE, 1
+    LD I, tick
+    DRW VC, VD, 5
+    ADD VC, 8
+    SE VC, 64
+    RET
+    LD VC, 0
+    ADD VD, 6
+    RET
+
+tick:
+    sprite .....#
+    sprite ....#.
+    sprite #..#..
+    sprite .##...
+    sprite ......
+cross:
+    sprite #...#
+    sprite .#.#.
+    sprite ..#..
+    sprite .#.#.
+    sprite #...#
+
+three:
+    sprite ###
+one:
+    sprite #
+blank:
+    db 0
+scratch:
+    db 0, 0
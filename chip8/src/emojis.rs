@@ -1,2 +1,2 @@
 // In a separate file, e.g., emojis.rs
-pub static EMOJIS: &[&str] = &["🧨", "🖊️", "👁️", "🕹️", "🖥️", "🏃"];
+pub static EMOJIS: &[&str] = &["🧨", "🖊️", "👁️", "🕹️", "🖥️", "🏃", "👋", "⚠️"];
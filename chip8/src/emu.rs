@@ -1,20 +1,61 @@
-pub(crate) mod cpu;
-mod gpu;
-mod input;
-mod iset;
-mod mem;
-mod timer;
+pub mod analysis;
+pub mod asm;
+pub mod audio;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod frontend;
+pub mod gpu;
+pub mod input;
+pub mod instruction;
+pub mod iset;
+pub mod mem;
+pub mod movie;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod romdb;
+pub mod state;
+pub mod timer;
+pub mod trace;
 
+use audio::{AudioSink, NullSink};
 use cpu::Cpu;
+use debugger::Debugger;
+use error::Chip8Error;
+use frontend::{Command, Frontend};
 use gpu::Gpu;
 use input::Keypad;
-use mem::{Memory, FONTS};
+use instruction::{decode, Instruction};
+use iset::OpCode;
+use mem::{Memory, BIG_FONTS, BIG_FONT_START, FONTS, FONT_START};
+use quirks::Preset;
+use rewind::Rewind;
+use std::{path::PathBuf, time::Duration};
 use timer::Timer;
+use trace::Tracer;
 
 #[derive(Debug)]
 pub struct Emulator {
     pub cpu: Cpu,
     pub running: bool,
+    /// Where the beep goes while the sound timer is non-zero, silent by default
+    pub audio: Box<dyn AudioSink>,
+    /// Where the quick-save slots live
+    pub state_dir: PathBuf,
+    /// Recent snapshots for `Command::Rewind`
+    pub rewind: Rewind,
+    /// Pausing and stepping
+    pub debugger: Debugger,
+    /// Logs each instruction as it runs, off by default
+    pub tracer: Option<Tracer>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Emulator {
@@ -22,16 +63,27 @@ impl Emulator {
         let delay_timer = Timer::new();
         let gpu = Gpu::new();
         let keypad = Keypad::new();
-        let rom_buffer = Vec::new();
         let sound_timer = Timer::new();
-        let memory = Memory::new(delay_timer, gpu, keypad, rom_buffer, sound_timer);
+        let memory = Memory::new(delay_timer, gpu, keypad, sound_timer);
         let cpu = Cpu::new(memory);
         Self {
             cpu,
             running: false,
+            audio: Box::new(NullSink),
+            state_dir: PathBuf::from("saves"),
+            rewind: Rewind::default(),
+            debugger: Debugger::default(),
+            tracer: None,
         }
     }
 
+    /// Follow `preset`'s quirks and resize memory to match, 64KiB for XO-CHIP.
+    /// Call this before loading the rom, shrinking memory drops anything past the new end.
+    pub fn set_preset(&mut self, preset: Preset) {
+        self.cpu.quirks = preset.quirks();
+        self.cpu.memory.ram.resize(preset.ram_size(), 0);
+    }
+
     /// The print_memory function has been moved to the Memory module
     pub fn print_memory(&self) {
         for (i, byte) in self.cpu.memory.ram.iter().enumerate() {
@@ -43,16 +95,167 @@ impl Emulator {
         println!();
     }
 
-    pub fn load_font(&mut self) -> Result<bool, bool> {
-        self.cpu.memory.ram[0..80].copy_from_slice(&FONTS);
-        Ok(true)
+    /// Execute `n` instructions, stopping early at the first one that fails, at 00FD or when
+    /// the debugger pauses
+    pub fn run_cycles(&mut self, n: usize) -> Result<(), Chip8Error> {
+        self.running = true;
+        for _ in 0..n {
+            if self.cpu.exited || self.debugger.paused || self.debugger.at_breakpoint(&self.cpu) {
+                self.running = false;
+                break;
+            }
+            match self.step_watched() {
+                Ok(true) => {
+                    self.running = false;
+                    break;
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    self.running = false;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
     }
 
-    /// Puts the rom_buffer into the memory
-    pub fn load_rom(&mut self) -> Result<bool, bool> {
-        let rom_length: usize = self.cpu.memory.rom.len();
-        self.cpu.memory.ram[512..512 + rom_length].copy_from_slice(&self.cpu.memory.rom);
-        self.cpu.memory.rom.clear();
+    /// Execute exactly one instruction for the debugger, without waiting for the vblank a
+    /// draw may be holding out for. Timers only move with frames, so they stay put.
+    pub fn step_instruction(&mut self) -> Result<(), Chip8Error> {
+        self.debugger.pause();
+        self.cpu.waiting_for_vblank = false;
+        self.step_watched()?;
+        Ok(())
+    }
+
+    /// One cpu step with the debugger looking on, true if it wants to stop
+    fn step_watched(&mut self) -> Result<bool, Chip8Error> {
+        let traced = self
+            .tracer
+            .as_mut()
+            .and_then(|tracer| tracer.before_step(&self.cpu));
+        let before = self.debugger.before_step(&self.cpu);
+        self.cpu.step()?;
+        if let (Some(tracer), Some(traced)) = (self.tracer.as_mut(), traced) {
+            tracer.after_step(&self.cpu, traced)?;
+        }
+        Ok(self.debugger.should_stop(&self.cpu, before))
+    }
+
+    /// Like `step_instruction`, but a call runs through to its return at full speed
+    pub fn step_over(&mut self) -> Result<(), Chip8Error> {
+        let pc = self.cpu.program_counter as usize;
+        let opcode = match self.cpu.memory.ram.get(pc..pc + 2) {
+            Some(&[high, low]) => OpCode(u16::from_be_bytes([high, low])),
+            _ => return self.step_instruction(),
+        };
+        match decode(opcode) {
+            Ok(Instruction::Call { .. }) => {
+                self.debugger.run_until_return(&self.cpu);
+                Ok(())
+            }
+            _ => self.step_instruction(),
+        }
+    }
+
+    /// Run one 60Hz frame: `cycles` instructions, a frame of audio, then the vertical blank
+    pub fn run_frame(&mut self, cycles: usize) -> Result<(), Chip8Error> {
+        self.run_cycles(cycles)?;
+        // the beep lasts as many frames as the sound timer was set to
+        self.audio.frame(self.cpu.memory.sound_timer.is_active())?;
+        self.cpu.vblank();
+        Ok(())
+    }
+
+    /// Drive `frontend` until it asks to quit or the program exits with 00FD.
+    /// Each frame runs `cycles_per_frame` instructions, is presented, then input is polled for
+    /// `frame_duration`.
+    pub fn run(
+        &mut self,
+        frontend: &mut dyn Frontend,
+        cycles_per_frame: usize,
+        frame_duration: Duration,
+    ) -> Result<(), Chip8Error> {
+        let mut rewinding = false;
+        while !self.cpu.exited {
+            if rewinding {
+                self.step_back()?;
+            } else if !self.debugger.paused {
+                // snapshot before the frame, so the first step back shows a change
+                if self.rewind.frame_finished() {
+                    let state = self.save_state();
+                    self.rewind.push(state);
+                }
+                self.run_frame(cycles_per_frame)?;
+            }
+            if let Some(reason) = self.debugger.take_new_stop() {
+                frontend.notify(&format!("Stopped: {reason}"));
+            }
+            frontend.present(self)?;
+            let commands = frontend.poll_input(&mut self.cpu.memory.pad, frame_duration)?;
+            rewinding = false;
+            for command in commands {
+                match command {
+                    Command::Quit => return Ok(()),
+                    Command::Rewind => rewinding = true,
+                    Command::Pause => self.debugger.pause(),
+                    Command::Continue => self.debugger.resume(),
+                    Command::Step => self.step_instruction()?,
+                    Command::StepOver => self.step_over()?,
+                    // a missing or broken slot shouldn't end the session
+                    Command::SaveSlot(slot) => match self.save_state_file(self.slot_path(slot)) {
+                        Ok(()) => frontend.notify(&format!("Saved slot {slot}")),
+                        Err(err) => frontend.notify(&format!("Save failed: {err:#}")),
+                    },
+                    Command::LoadSlot(slot) => match self.load_state_file(self.slot_path(slot)) {
+                        Ok(()) => frontend.notify(&format!("Loaded slot {slot}")),
+                        Err(err) => frontend.notify(&format!("Load failed: {err:#}")),
+                    },
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Go back to the newest rewind snapshot, false once there are none left.
+    /// The keypad is left as it is, it belongs to whoever is holding the keys now.
+    pub fn step_back(&mut self) -> Result<bool, Chip8Error> {
+        let Some(state) = self.rewind.pop() else {
+            return Ok(false);
+        };
+        let pad = self.cpu.memory.pad;
+        self.load_state(&state)
+            .map_err(|err| Chip8Error::BadSnapshot(format!("{err:#}")))?;
+        self.cpu.memory.pad = pad;
         Ok(true)
     }
+
+    /// File backing quick-save `slot`
+    pub fn slot_path(&self, slot: u8) -> PathBuf {
+        self.state_dir.join(format!("slot{slot}.c8s"))
+    }
+
+    /// Copy both fonts into the interpreter area, which every memory size has room for
+    pub fn load_font(&mut self) {
+        self.cpu.memory.ram[FONT_START..FONT_START + FONTS.len()].copy_from_slice(&FONTS);
+        self.cpu.memory.ram[BIG_FONT_START..BIG_FONT_START + BIG_FONTS.len()]
+            .copy_from_slice(&BIG_FONTS);
+    }
+
+    /// Copy `rom` into memory at `start` and point the program counter at it. `start` is
+    /// PROGRAM_START for almost everything, ETI_660_START for ETI 660 programs.
+    /// Call `set_preset` first, it decides how much memory there is to fill.
+    pub fn load_rom(&mut self, rom: &[u8], start: u16) -> Result<(), Chip8Error> {
+        let begin = start as usize;
+        let max = self.cpu.memory.ram.len().saturating_sub(begin);
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
+        self.cpu.memory.ram[begin..begin + rom.len()].copy_from_slice(rom);
+        self.cpu.program_counter = start;
+        Ok(())
+    }
 }
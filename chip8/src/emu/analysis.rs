@@ -0,0 +1,301 @@
+// Static analysis of a rom: which platform its instructions need, which quirks its behaviour
+// hangs on and what it uses that the cpu can't run. Only code reachable from the entry point
+// is looked at, see `disasm::trace_reachable`, so sprite data that happens to look like a
+// SUPER-CHIP opcode doesn't count. Targets of Bnnn jumps can't be followed and are missed.
+use std::{collections::BTreeMap, fmt};
+
+use super::{
+    disasm::{self, Reachable},
+    instruction::{decode, Instruction},
+    iset::OpCode,
+    mem::RAM_SIZE,
+    quirks::Preset,
+};
+
+/// The machines roms are written for, each a superset of the one before
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    /// The quirk preset to run the platform's roms under
+    pub fn preset(self) -> Preset {
+        match self {
+            Platform::Chip8 => Preset::CosmacVip,
+            Platform::SuperChip => Preset::SuperChip,
+            Platform::XoChip => Preset::XoChip,
+        }
+    }
+
+    /// The platform whose roms `preset` runs
+    pub fn of(preset: Preset) -> Self {
+        match preset {
+            Preset::CosmacVip | Preset::Chip48 => Platform::Chip8,
+            Preset::SuperChip => Platform::SuperChip,
+            Preset::XoChip => Platform::XoChip,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        })
+    }
+}
+
+/// A reachable instruction the cpu can't run as the rom means it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsupported {
+    pub address: u16,
+    pub opcode: u16,
+    pub reason: &'static str,
+}
+
+/// What `analyze` found out about a rom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /// Bytes in the rom
+    pub size: usize,
+    /// Bytes of it that are reachable instructions, the rest is data
+    pub code_size: usize,
+    /// The oldest platform with every instruction the rom uses
+    pub platform: Platform,
+    /// Why the rom needs `platform`, e.g. `HIGH at 0x200`. `None` for CHIP-8.
+    pub platform_reason: Option<String>,
+    /// How many of each instruction there are, by `Instruction::pattern`
+    pub histogram: BTreeMap<&'static str, usize>,
+    /// Addresses of the instructions that behave differently with each `Quirks` field, keyed by
+    /// the field's name
+    pub quirks: BTreeMap<&'static str, Vec<u16>>,
+    pub unsupported: Vec<Unsupported>,
+}
+
+impl Analysis {
+    /// Pattern and count of each instruction the rom uses, most used first
+    pub fn most_used(&self) -> Vec<(&'static str, usize)> {
+        let mut counts: Vec<_> = self.histogram.iter().map(|(&p, &n)| (p, n)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+}
+
+/// Scan `rom`, loaded at `origin`
+pub fn analyze(rom: &[u8], origin: u16) -> Analysis {
+    let Reachable { code, unknown } = disasm::trace_reachable(rom, origin);
+
+    let mut histogram = BTreeMap::new();
+    let mut quirks: BTreeMap<&'static str, Vec<u16>> = BTreeMap::new();
+    let mut unsupported = Vec::new();
+    let mut code_size = 0;
+    let mut platform = Platform::Chip8;
+    let mut platform_reason = None;
+    // keeps the first reason for the newest platform, the addresses are visited in order
+    let mut need = |needs: Platform, reason: &dyn Fn() -> String| {
+        if needs > platform {
+            platform = needs;
+            platform_reason = Some(reason());
+        }
+    };
+    // CHIP-8 and SUPER-CHIP have 4KiB of memory, a rom that runs past its end needs XO-CHIP's 64KiB
+    if origin as usize + rom.len() > RAM_SIZE {
+        need(Platform::XoChip, &|| format!("{} bytes", rom.len()));
+    }
+
+    for &address in &code {
+        let Some(OpCode(opcode)) = disasm::opcode_at(rom, origin, address) else {
+            continue;
+        };
+        let Ok(instruction) = decode(OpCode(opcode)) else {
+            continue;
+        };
+        code_size += instruction.size() as usize;
+        *histogram.entry(instruction.pattern()).or_insert(0) += 1;
+        need(needs(instruction), &|| {
+            format!("{instruction} at {address:#05X}")
+        });
+        for &quirk in quirks_of(instruction) {
+            quirks.entry(quirk).or_default().push(address);
+        }
+        if let Instruction::Sys { nnn } = instruction {
+            let reason = match nnn & 0xFF0 {
+                0x0D0 => "00Dn scrolls up on XO-CHIP, it runs as a jump to machine code here",
+                _ => "0nnn calls a machine code routine, it runs as a plain jump here",
+            };
+            unsupported.push(Unsupported {
+                address,
+                opcode,
+                reason,
+            });
+        }
+    }
+
+    for &address in &unknown {
+        let Some(OpCode(opcode)) = disasm::opcode_at(rom, origin, address) else {
+            continue;
+        };
+        let (needs, reason) = match opcode {
+            0xF002 => (Platform::XoChip, "F002 loads an XO-CHIP audio pattern"),
+            _ if opcode & 0xF0FF == 0xF03A => (Platform::XoChip, "Fx3A sets the XO-CHIP pitch"),
+            _ => (
+                Platform::Chip8,
+                "not an instruction on any supported platform",
+            ),
+        };
+        need(needs, &|| format!("{opcode:04X} at {address:#05X}"));
+        unsupported.push(Unsupported {
+            address,
+            opcode,
+            reason,
+        });
+    }
+    unsupported.sort_by_key(|unsupported| unsupported.address);
+
+    Analysis {
+        size: rom.len(),
+        code_size,
+        platform,
+        platform_reason,
+        histogram,
+        quirks,
+        unsupported,
+    }
+}
+
+/// The oldest platform `instruction` exists on
+fn needs(instruction: Instruction) -> Platform {
+    use Instruction::*;
+    match instruction {
+        StoreRange { .. } | LoadRange { .. } | LongIndex | Plane { .. } => Platform::XoChip,
+        // SUPER-CHIP has 8 flag registers, XO-CHIP 16
+        StoreFlags { x } | LoadFlags { x } if x > 7 => Platform::XoChip,
+        ScrollDown { .. }
+        | ScrollRight
+        | ScrollLeft
+        | Exit
+        | LowRes
+        | HighRes
+        | Draw { n: 0, .. }
+        | LoadBigFont { .. }
+        | StoreFlags { .. }
+        | LoadFlags { .. } => Platform::SuperChip,
+        _ => Platform::Chip8,
+    }
+}
+
+/// The `Quirks` fields that change what `instruction` does
+fn quirks_of(instruction: Instruction) -> &'static [&'static str] {
+    use Instruction::*;
+    match instruction {
+        // shifting a register into itself comes out the same either way
+        ShiftRight { x, y } | ShiftLeft { x, y } if x != y => &["shift_uses_vy"],
+        StoreRegs { .. } | LoadRegs { .. } => &["load_store_increments_i"],
+        // BXnn reads vX, which is v0 again when X is 0
+        JumpOffset { nnn } if nnn & 0xF00 != 0 => &["jump_uses_vx"],
+        Draw { .. } => &["clip_sprites", "display_wait"],
+        Or { .. } | And { .. } | Xor { .. } => &["logic_resets_vf"],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod analysistests {
+    use super::{analyze, Platform};
+    use crate::emu::disasm::DEFAULT_ORIGIN;
+
+    const MAZE: &[u8] = &[
+        0xA2, 0x1E, 0xC2, 0x01, 0x32, 0x01, 0xA2, 0x1A, 0xD0, 0x14, 0x70, 0x04, 0x30, 0x40, 0x12,
+        0x00, 0x60, 0x00, 0x71, 0x04, 0x31, 0x20, 0x12, 0x00, 0x12, 0x18, 0x80, 0x40, 0x20, 0x10,
+        0x20, 0x40, 0x80, 0x10,
+    ];
+
+    #[test]
+    fn test_chip8_rom() {
+        let analysis = analyze(MAZE, DEFAULT_ORIGIN);
+        assert_eq!(analysis.size, 34);
+        assert_eq!(analysis.code_size, 26);
+        assert_eq!(analysis.platform, Platform::Chip8);
+        assert_eq!(analysis.platform_reason, None);
+        assert_eq!(analysis.histogram["1nnn"], 3);
+        assert_eq!(analysis.most_used()[0], ("1nnn", 3));
+        // 2010 in the sprite data would decode as a call, but it's never reached
+        assert!(!analysis.histogram.contains_key("2nnn"));
+        assert_eq!(
+            analysis.quirks.keys().copied().collect::<Vec<_>>(),
+            ["clip_sprites", "display_wait"]
+        );
+        assert!(analysis.unsupported.is_empty());
+    }
+
+    #[test]
+    fn test_detects_platform() {
+        let schip = [
+            0x00, 0xFF, // 200: HIGH
+            0xD0, 0x10, // 202: DRW V0, V1, 0
+            0x12, 0x04, // 204: JP 0x204
+        ];
+        let analysis = analyze(&schip, DEFAULT_ORIGIN);
+        assert_eq!(analysis.platform, Platform::SuperChip);
+        assert_eq!(analysis.platform_reason.as_deref(), Some("HIGH at 0x200"));
+        assert_eq!(analysis.platform.preset().name(), "schip");
+
+        let xochip = [
+            0x00, 0xFF, // 200: HIGH
+            0xF1, 0x01, // 202: PLANE 1
+            0x12, 0x04, // 204: JP 0x204
+        ];
+        let analysis = analyze(&xochip, DEFAULT_ORIGIN);
+        assert_eq!(analysis.platform, Platform::XoChip);
+        assert_eq!(
+            analysis.platform_reason.as_deref(),
+            Some("PLANE 1 at 0x202")
+        );
+
+        // only XO-CHIP has room for this much
+        let big = analyze(&[0x12; 0x1000], DEFAULT_ORIGIN);
+        assert_eq!(big.platform, Platform::XoChip);
+        assert_eq!(big.platform_reason.as_deref(), Some("4096 bytes"));
+    }
+
+    #[test]
+    fn test_quirk_dependencies() {
+        let rom = [
+            0x81, 0x26, // 200: SHR V1, V2
+            0x81, 0x1E, // 202: SHL V1, V1, the same either way
+            0xF3, 0x55, // 204: LD [I], V3
+            0xD0, 0x15, // 206: DRW V0, V1, 5
+            0xB0, 0x10, // 208: JP V0, 0x010, the same either way
+        ];
+        let analysis = analyze(&rom, DEFAULT_ORIGIN);
+        assert_eq!(analysis.quirks["shift_uses_vy"], [0x200]);
+        assert_eq!(analysis.quirks["load_store_increments_i"], [0x204]);
+        assert!(!analysis.quirks.contains_key("jump_uses_vx"));
+        assert_eq!(analysis.quirks["clip_sprites"], [0x206]);
+        assert_eq!(analysis.quirks["display_wait"], [0x206]);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let rom = [
+            0x30, 0x00, // 200: SE V0, 0x00
+            0x02, 0x10, // 202: SYS 0x210
+            0xF0, 0x02, // 204: audio pattern
+            0x05, 0x55, // 206: data after the SYS jump
+        ];
+        let analysis = analyze(&rom, DEFAULT_ORIGIN);
+        let found: Vec<(u16, u16)> = analysis
+            .unsupported
+            .iter()
+            .map(|unsupported| (unsupported.address, unsupported.opcode))
+            .collect();
+        assert_eq!(found, [(0x202, 0x0210), (0x204, 0xF002)]);
+        assert_eq!(analysis.platform, Platform::XoChip);
+        assert_eq!(analysis.platform_reason.as_deref(), Some("F002 at 0x204"));
+    }
+}
@@ -0,0 +1,498 @@
+// Assembles chip8 source text into a rom.
+//
+// The syntax is the one `disasm` prints, so disassembled roms can be edited and rebuilt:
+//
+//     ; comments run to the end of the line
+//     SPEED = 4              ; constants
+//     start:                 ; labels, optionally followed by an instruction
+//         LD V0, SPEED
+//         LD I, ball
+//         DRW V0, V1, 2
+//         JP start
+//     ball:
+//         sprite .##.....    ; one sprite row per line, '#' is a set pixel
+//         db 0x60, 0b1100    ; raw bytes
+//         dw 0x1234          ; big-endian words
+//         LD I, LONG data    ; XO-CHIP F000 nnnn, assembled to 4 bytes
+//         LD [I], V0-V3      ; XO-CHIP register ranges
+use std::{collections::HashMap, fmt};
+
+use super::{instruction::Instruction, mem::RAM_SIZE};
+
+/// Every mnemonic the assembler understands, used to tell bad operands from typos.
+const MNEMONICS: [&str; 27] = [
+    "SYS", "CLS", "RET", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR",
+    "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH",
+    "PLANE",
+];
+
+/// An error in the source, with the 1-based line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// A source line that emits bytes, kept between the two passes.
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+/// Operands after registers and keywords have been recognised and symbols resolved.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Operand {
+    V(u8),
+    I,
+    AtI,
+    DT,
+    ST,
+    K,
+    F,
+    HF,
+    B,
+    R,
+    /// `Vx-Vy`, an XO-CHIP register range
+    VRange(u8, u8),
+    /// `LONG addr`, the 16-bit address of F000 nnnn
+    Long(u16),
+    Value(u32),
+}
+
+/// Assemble `source` into the bytes of a rom that will be loaded at `origin`.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let mut symbols: HashMap<&str, u32> = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = origin as usize;
+
+    // Pass 1: find where every label lands and evaluate constants
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| AsmError { line, message };
+        let mut text = raw.split(';').next().unwrap_or("").trim();
+
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_identifier(label) {
+                return Err(error(format!("invalid label name `{label}`")));
+            }
+            if symbols.insert(label, address as u32).is_some() {
+                return Err(error(format!("`{label}` is defined more than once")));
+            }
+            text = rest.trim();
+        }
+
+        if let Some((name, value)) = text.split_once('=') {
+            let name = name.trim();
+            if !is_identifier(name) {
+                return Err(error(format!("invalid constant name `{name}`")));
+            }
+            let value = resolve(value.trim(), &symbols).map_err(error)?;
+            if symbols.insert(name, value).is_some() {
+                return Err(error(format!("`{name}` is defined more than once")));
+            }
+            continue;
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let operands: Vec<&str> = match mnemonic.as_str() {
+            "SPRITE" => vec![rest.trim()],
+            _ => rest
+                .split(',')
+                .map(str::trim)
+                .filter(|operand| !operand.is_empty())
+                .collect(),
+        };
+        address += match mnemonic.as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            "SPRITE" => 1,
+            "LD" if operands.iter().any(|operand| is_long(operand)) => 4,
+            _ => 2,
+        };
+        if address > RAM_SIZE {
+            return Err(error(format!(
+                "program is too large, it runs past the end of memory at {:#05X}",
+                RAM_SIZE - 1
+            )));
+        }
+        statements.push(Statement {
+            line,
+            mnemonic,
+            operands,
+        });
+    }
+
+    // Pass 2: every label is known now, so operands can be resolved and encoded
+    let mut rom = Vec::with_capacity(address - origin as usize);
+    for statement in statements {
+        let error = |message: String| AsmError {
+            line: statement.line,
+            message,
+        };
+        match statement.mnemonic.as_str() {
+            "DB" => {
+                for operand in &statement.operands {
+                    let value = resolve(operand, &symbols).map_err(error)?;
+                    rom.push(byte(value).map_err(error)?);
+                }
+            }
+            "DW" => {
+                for operand in &statement.operands {
+                    let value = resolve(operand, &symbols).map_err(error)?;
+                    let word = u16::try_from(value).map_err(|_| {
+                        error(format!("word {value:#X} is out of range (max 0xFFFF)"))
+                    })?;
+                    rom.extend(word.to_be_bytes());
+                }
+            }
+            "SPRITE" => rom.push(sprite_row(statement.operands[0]).map_err(error)?),
+            mnemonic => {
+                let operands = statement
+                    .operands
+                    .iter()
+                    .map(|operand| parse_operand(operand, &symbols))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                let instruction = parse_instruction(mnemonic, &operands).map_err(error)?;
+                rom.extend(instruction.encode().0.to_be_bytes());
+                // F000's address follows it as a second word
+                if let [_, Operand::Long(nnnn)] = operands[..] {
+                    rom.extend(nnnn.to_be_bytes());
+                }
+            }
+        }
+    }
+    Ok(rom)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parses `0x1F`, `0b0101` or decimal numbers.
+fn parse_number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// A number, label or constant.
+fn resolve(text: &str, symbols: &HashMap<&str, u32>) -> Result<u32, String> {
+    if let Some(value) = parse_number(text) {
+        return Ok(value);
+    }
+    if !is_identifier(text) {
+        return Err(format!("invalid number `{text}`"));
+    }
+    symbols
+        .get(text)
+        .copied()
+        .ok_or_else(|| format!("unknown label or constant `{text}`"))
+}
+
+/// A row of up to 8 pixels such as `.##.....`, left aligned like the screen.
+fn sprite_row(text: &str) -> Result<u8, String> {
+    if text.is_empty() || text.len() > 8 {
+        return Err(format!("sprite rows need 1 to 8 pixels, got `{text}`"));
+    }
+    text.chars()
+        .enumerate()
+        .try_fold(0u8, |row, (bit, pixel)| match pixel {
+            '#' => Ok(row | 0x80 >> bit),
+            '.' => Ok(row),
+            _ => Err(format!("sprite pixels are '#' or '.', got `{pixel}`")),
+        })
+}
+
+/// `LONG addr` operands make an LD 4 bytes long
+fn is_long(operand: &str) -> bool {
+    operand
+        .get(..5)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("LONG "))
+}
+
+/// Parses a single register such as `VA`
+fn register(text: &str) -> Option<u8> {
+    let upper = text.to_ascii_uppercase();
+    match upper.strip_prefix('V').map(|n| u8::from_str_radix(n, 16)) {
+        Some(Ok(x)) if upper.len() == 2 => Some(x),
+        _ => None,
+    }
+}
+
+fn parse_operand(text: &str, symbols: &HashMap<&str, u32>) -> Result<Operand, String> {
+    if is_long(text) {
+        let value = resolve(text[5..].trim(), symbols)?;
+        let nnnn = u16::try_from(value)
+            .map_err(|_| format!("long address {value:#X} is out of range (max 0xFFFF)"))?;
+        return Ok(Operand::Long(nnnn));
+    }
+    if let Some((x, y)) = text.split_once('-') {
+        if let (Some(x), Some(y)) = (register(x.trim()), register(y.trim())) {
+            return Ok(Operand::VRange(x, y));
+        }
+    }
+    let upper = text.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::AtI,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::HF,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        _ => match register(text) {
+            Some(x) => Operand::V(x),
+            None => Operand::Value(resolve(text, symbols)?),
+        },
+    };
+    Ok(operand)
+}
+
+fn address(value: u32) -> Result<u16, String> {
+    match u16::try_from(value) {
+        Ok(nnn) if nnn <= 0xFFF => Ok(nnn),
+        _ => Err(format!("address {value:#X} is out of range (max 0xFFF)")),
+    }
+}
+
+fn byte(value: u32) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("byte {value:#X} is out of range (max 0xFF)"))
+}
+
+fn nibble(value: u32) -> Result<u8, String> {
+    match u8::try_from(value) {
+        Ok(n) if n <= 0xF => Ok(n),
+        _ => Err(format!("sprite height {value} is out of range (max 15)")),
+    }
+}
+
+fn parse_instruction(mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+    use Instruction::*;
+    use Operand::{AtI, Long, VRange, Value, B, DT, F, HF, I, K, R, ST, V};
+    let instruction = match (mnemonic, operands) {
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("SCD", [Value(n)]) => ScrollDown { n: nibble(*n)? },
+        ("SCR", []) => ScrollRight,
+        ("SCL", []) => ScrollLeft,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => LowRes,
+        ("HIGH", []) => HighRes,
+        ("SYS", [Value(a)]) => Sys { nnn: address(*a)? },
+        ("JP", [Value(a)]) => Jump { nnn: address(*a)? },
+        ("JP", [V(0), Value(a)]) => JumpOffset { nnn: address(*a)? },
+        ("CALL", [Value(a)]) => Call { nnn: address(*a)? },
+        ("SE", [V(x), Value(b)]) => SkipEqImm {
+            x: *x,
+            nn: byte(*b)?,
+        },
+        ("SE", [V(x), V(y)]) => SkipEqReg { x: *x, y: *y },
+        ("SNE", [V(x), Value(b)]) => SkipNeImm {
+            x: *x,
+            nn: byte(*b)?,
+        },
+        ("SNE", [V(x), V(y)]) => SkipNeReg { x: *x, y: *y },
+        ("LD", [V(x), Value(b)]) => LoadImm {
+            x: *x,
+            nn: byte(*b)?,
+        },
+        ("LD", [V(x), V(y)]) => LoadReg { x: *x, y: *y },
+        ("LD", [I, Value(a)]) => LoadIndex { nnn: address(*a)? },
+        ("LD", [I, Long(_)]) => LongIndex,
+        ("LD", [AtI, VRange(x, y)]) => StoreRange { x: *x, y: *y },
+        ("LD", [VRange(x, y), AtI]) => LoadRange { x: *x, y: *y },
+        ("PLANE", [Value(n)]) => Plane {
+            n: match *n {
+                0..=3 => *n as u8,
+                _ => return Err(format!("plane mask {n} is out of range (max 3)")),
+            },
+        },
+        ("LD", [V(x), DT]) => LoadDelay { x: *x },
+        ("LD", [V(x), K]) => WaitKey { x: *x },
+        ("LD", [DT, V(x)]) => SetDelay { x: *x },
+        ("LD", [ST, V(x)]) => SetSound { x: *x },
+        ("LD", [F, V(x)]) => LoadFont { x: *x },
+        ("LD", [HF, V(x)]) => LoadBigFont { x: *x },
+        ("LD", [B, V(x)]) => StoreBcd { x: *x },
+        ("LD", [AtI, V(x)]) => StoreRegs { x: *x },
+        ("LD", [V(x), AtI]) => LoadRegs { x: *x },
+        ("LD", [R, V(x)]) => StoreFlags { x: *x },
+        ("LD", [V(x), R]) => LoadFlags { x: *x },
+        ("ADD", [V(x), Value(b)]) => AddImm {
+            x: *x,
+            nn: byte(*b)?,
+        },
+        ("ADD", [V(x), V(y)]) => AddReg { x: *x, y: *y },
+        ("ADD", [I, V(x)]) => AddIndex { x: *x },
+        ("OR", [V(x), V(y)]) => Or { x: *x, y: *y },
+        ("AND", [V(x), V(y)]) => And { x: *x, y: *y },
+        ("XOR", [V(x), V(y)]) => Xor { x: *x, y: *y },
+        ("SUB", [V(x), V(y)]) => SubReg { x: *x, y: *y },
+        ("SUBN", [V(x), V(y)]) => SubN { x: *x, y: *y },
+        ("SHR", [V(x)]) => ShiftRight { x: *x, y: *x },
+        ("SHR", [V(x), V(y)]) => ShiftRight { x: *x, y: *y },
+        ("SHL", [V(x)]) => ShiftLeft { x: *x, y: *x },
+        ("SHL", [V(x), V(y)]) => ShiftLeft { x: *x, y: *y },
+        ("RND", [V(x), Value(b)]) => Random {
+            x: *x,
+            nn: byte(*b)?,
+        },
+        ("DRW", [V(x), V(y), Value(n)]) => Draw {
+            x: *x,
+            y: *y,
+            n: nibble(*n)?,
+        },
+        ("SKP", [V(x)]) => SkipKey { x: *x },
+        ("SKNP", [V(x)]) => SkipNotKey { x: *x },
+        _ if MNEMONICS.contains(&mnemonic) => {
+            return Err(format!("invalid operands for {mnemonic}"));
+        }
+        _ => return Err(format!("unknown instruction `{mnemonic}`")),
+    };
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod asmtests {
+    use super::{assemble, AsmError};
+    use crate::emu::disasm::{disassemble, DEFAULT_ORIGIN};
+
+    #[test]
+    fn test_assemble_program() {
+        let source = "
+            ; bounce a ball
+            SPEED = 4
+            start:  LD V0, SPEED
+                    LD I, ball
+                    DRW V0, V1, 2
+                    SHR V3
+                    ld [i], vf
+                    JP start
+            ball:
+                    sprite .##.....
+                    sprite #..#
+                    db 0x60, 0b1100, 255
+                    dw 0x1234
+        ";
+        let rom = assemble(source, DEFAULT_ORIGIN).unwrap();
+        assert_eq!(
+            rom,
+            [
+                0x60, 0x04, // LD V0, SPEED
+                0xA2, 0x0C, // LD I, ball
+                0xD0, 0x12, // DRW V0, V1, 2
+                0x83, 0x36, // SHR V3
+                0xFF, 0x55, // LD [I], VF
+                0x12, 0x00, // JP start
+                0x60, 0x90, // sprite rows
+                0x60, 0x0C, 0xFF, // db
+                0x12, 0x34, // dw
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_superchip() {
+        let source =
+            "HIGH\nSCD 3\nSCR\nSCL\nLD HF, V2\nLD R, V7\nLD V7, R\nDRW V0, V1, 0\nLOW\nEXIT";
+        let rom = assemble(source, DEFAULT_ORIGIN).unwrap();
+        assert_eq!(
+            rom,
+            [
+                0x00, 0xFF, 0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFC, 0xF2, 0x30, 0xF7, 0x75, 0xF7, 0x85,
+                0xD0, 0x10, 0x00, 0xFE, 0x00, 0xFD,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_xochip() {
+        let source = "
+            start:  LD I, LONG data
+                    PLANE 3
+                    LD [I], V0-V3
+                    LD V3-V0, [I]
+            data:   db 1
+        ";
+        let rom = assemble(source, DEFAULT_ORIGIN).unwrap();
+        assert_eq!(
+            rom,
+            [0xF0, 0x00, 0x02, 0x0A, 0xF3, 0x01, 0x50, 0x32, 0x53, 0x03, 0x01]
+        );
+        let error = assemble("PLANE 4", DEFAULT_ORIGIN).unwrap_err();
+        assert_eq!(error.message, "plane mask 4 is out of range (max 3)");
+    }
+
+    #[test]
+    fn test_disassembly_roundtrip() {
+        let maze = std::fs::read("roms/maze.ch8").unwrap();
+        let source = disassemble(&maze, DEFAULT_ORIGIN).join("\n");
+        assert_eq!(assemble(&source, DEFAULT_ORIGIN).unwrap(), maze);
+    }
+
+    #[test]
+    fn test_errors_have_line_numbers() {
+        let error = |source: &str| assemble(source, DEFAULT_ORIGIN).unwrap_err();
+        assert_eq!(
+            error("CLS\nJP 0x1000"),
+            AsmError {
+                line: 2,
+                message: "address 0x1000 is out of range (max 0xFFF)".to_string()
+            }
+        );
+        assert_eq!(
+            error("LD V0, 256").to_string(),
+            "line 1: byte 0x100 is out of range (max 0xFF)"
+        );
+        assert_eq!(
+            error("\n\nDRW V0, V1").to_string(),
+            "line 3: invalid operands for DRW"
+        );
+        assert_eq!(
+            error("JP nowhere").to_string(),
+            "line 1: unknown label or constant `nowhere`"
+        );
+        assert_eq!(
+            error("MOV V0, V1").to_string(),
+            "line 1: unknown instruction `MOV`"
+        );
+        assert_eq!(
+            error("a:\na:").to_string(),
+            "line 2: `a` is defined more than once"
+        );
+        assert_eq!(
+            error("sprite #..x").to_string(),
+            "line 1: sprite pixels are '#' or '.', got `x`"
+        );
+    }
+
+    #[test]
+    fn test_program_too_large() {
+        let source = "db 0\n".repeat(4096 - 0x200) + "CLS";
+        let error = assemble(&source, DEFAULT_ORIGIN).unwrap_err();
+        assert_eq!(error.line, 4096 - 0x200 + 1);
+    }
+}
@@ -0,0 +1,144 @@
+// Sound output. The chip8 only knows one sound, a beep that plays while the sound timer is
+// non-zero, so a sink is told once per 60Hz frame whether to beep or stay silent.
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::error::Chip8Error;
+
+type Result<T> = std::result::Result<T, Chip8Error>;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+/// The timers count at 60Hz, so a frame is the smallest unit a beep can last
+pub const FRAMES_PER_SECOND: u32 = 60;
+pub const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / FRAMES_PER_SECOND;
+/// Pitch of the beep, the VIP's was fixed in hardware
+pub const BEEP_HZ: u32 = 440;
+/// Peak of the square wave, a quarter of full scale to be kind to ears
+const AMPLITUDE: i16 = i16::MAX / 4;
+
+/// Somewhere the beep goes, driven by `Emulator::run_frame`
+pub trait AudioSink: fmt::Debug {
+    /// Play one 60Hz frame, beeping if the sound timer was non-zero during it
+    fn frame(&mut self, beeping: bool) -> Result<()>;
+
+    /// Flush anything buffered once the emulator stops
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Throws the sound away, the default when nobody is listening
+#[derive(Debug, Default)]
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn frame(&mut self, _beeping: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Renders the beep as a 16-bit mono PCM WAV, silence included, so its timing can be checked
+#[derive(Debug)]
+pub struct WavSink<W: Write + Seek> {
+    writer: W,
+    /// Samples written so far, the square wave's phase carries across frames
+    samples: u32,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek + fmt::Debug> WavSink<W> {
+    /// Start a WAV in `writer`, the sizes in its header are filled in by `finish`
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        write_header(&mut writer, 0)?;
+        Ok(Self { writer, samples: 0 })
+    }
+
+    /// Give back the writer, call `finish` first so the header is complete
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek + fmt::Debug> AudioSink for WavSink<W> {
+    fn frame(&mut self, beeping: bool) -> Result<()> {
+        let mut bytes = Vec::with_capacity(SAMPLES_PER_FRAME as usize * 2);
+        for n in self.samples..self.samples + SAMPLES_PER_FRAME {
+            // the wave flips every half period
+            let half_periods = n as u64 * BEEP_HZ as u64 * 2 / SAMPLE_RATE as u64;
+            let high = half_periods.is_multiple_of(2);
+            let sample = match (beeping, high) {
+                (false, _) => 0,
+                (true, true) => AMPLITUDE,
+                (true, false) => -AMPLITUDE,
+            };
+            bytes.extend(sample.to_le_bytes());
+        }
+        self.writer.write_all(&bytes)?;
+        self.samples += SAMPLES_PER_FRAME;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.samples * 2)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// The 44 byte RIFF header for `data_len` bytes of 16-bit mono samples
+fn write_header(writer: &mut impl Write, data_len: u32) -> io::Result<()> {
+    let block_align: u16 = 2;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&1u16.to_le_bytes())?; // mono
+    writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())
+}
+
+#[cfg(test)]
+mod audiotests {
+    use super::{AudioSink, WavSink, SAMPLES_PER_FRAME};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_wav_sink_writes_frames() {
+        let mut sink = WavSink::new(Cursor::new(Vec::new())).unwrap();
+        sink.frame(true).unwrap();
+        sink.frame(false).unwrap();
+        sink.finish().unwrap();
+        let wav = sink.into_inner().into_inner();
+
+        let data_len = SAMPLES_PER_FRAME as usize * 2 * 2;
+        assert_eq!(wav.len(), 44 + data_len);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav[4..8], (36 + data_len as u32).to_le_bytes());
+        assert_eq!(wav[40..44], (data_len as u32).to_le_bytes());
+
+        let samples: Vec<i16> = wav[44..]
+            .chunks(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        let (beep, silence) = samples.split_at(SAMPLES_PER_FRAME as usize);
+        assert!(beep[0] > 0);
+        assert!(beep.iter().any(|&sample| sample < 0));
+        assert!(silence.iter().all(|&sample| sample == 0));
+    }
+}
@@ -0,0 +1,629 @@
+// Execution control for debugging: pausing, single steps, stepping over subroutine calls,
+// breakpoints and watchpoints. `Emulator::run_cycles` shows the debugger every instruction
+// before and after it executes, and stops running once the debugger pauses.
+use std::{collections::BTreeSet, fmt, ops::RangeInclusive, str::FromStr};
+
+use super::{
+    cpu::Cpu,
+    instruction::{decode, Instruction},
+    iset::OpCode,
+};
+
+/// Something the debugger can read off the cpu, for register watchpoints and conditions
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+    Delay,
+    Sound,
+}
+
+impl Register {
+    pub fn read(self, cpu: &Cpu) -> u16 {
+        match self {
+            Register::V(x) => cpu.registers[x as usize & 0xF] as u16,
+            Register::I => cpu.index_register,
+            Register::Pc => cpu.program_counter,
+            Register::Delay => cpu.memory.delay_timer.get() as u16,
+            Register::Sound => cpu.memory.sound_timer.get() as u16,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{x:X}"),
+            Register::I => f.write_str("I"),
+            Register::Pc => f.write_str("PC"),
+            Register::Delay => f.write_str("DT"),
+            Register::Sound => f.write_str("ST"),
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let upper = name.trim().to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => Ok(Register::I),
+            "PC" => Ok(Register::Pc),
+            "DT" => Ok(Register::Delay),
+            "ST" => Ok(Register::Sound),
+            _ => upper
+                .strip_prefix('V')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .map(Register::V)
+                .ok_or_else(|| {
+                    format!("unknown register `{name}`, expected V0-VF, I, PC, DT or ST")
+                }),
+        }
+    }
+}
+
+/// Which memory accesses a watchpoint stops on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn includes(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+/// Stop when an instruction reads or writes any of `addresses`.
+/// Written `0x300-0x30F:w`, with `:r`, `:w` or `:rw` (the default) for the access.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryWatch {
+    pub addresses: RangeInclusive<u16>,
+    pub access: Access,
+}
+
+impl FromStr for MemoryWatch {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (range, access) = text.split_once(':').unwrap_or((text, "rw"));
+        let access = match access {
+            "r" => Access::Read,
+            "w" => Access::Write,
+            "rw" => Access::ReadWrite,
+            _ => return Err(format!("unknown access `{access}`, expected r, w or rw")),
+        };
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let (start, end) = (parse_number(start)?, parse_number(end)?);
+        if start > end {
+            return Err(format!("watch range `{range}` ends before it starts"));
+        }
+        Ok(Self {
+            addresses: start..=end,
+            access,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    const ALL: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn symbol(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(_, comparison)| *comparison == self)
+            .map_or("?", |(symbol, _)| symbol)
+    }
+}
+
+/// Stop when a comparison like `V3 == 0x10` becomes true
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        let (lhs, rhs) = (self.register.read(cpu), self.value);
+        match self.comparison {
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+            Comparison::Less => lhs < rhs,
+            Comparison::LessOrEqual => lhs <= rhs,
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterOrEqual => lhs >= rhs,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = self.comparison.symbol();
+        write!(f, "{} {symbol} {:#X}", self.register, self.value)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (register, comparison, value) = Comparison::ALL
+            .iter()
+            .find_map(|&(symbol, comparison)| {
+                let (lhs, rhs) = text.split_once(symbol)?;
+                Some((lhs, comparison, rhs))
+            })
+            .ok_or_else(|| format!("`{text}` has no comparison, e.g. `V3 == 0x10`"))?;
+        Ok(Self {
+            register: register.parse()?,
+            comparison,
+            value: parse_number(value)?,
+        })
+    }
+}
+
+/// `0x` hex or decimal
+pub fn parse_number(text: &str) -> Result<u16, String> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("`{text}` is not a number"))
+}
+
+/// Why the debugger stopped the cpu. `pc` is the address of the instruction responsible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// Execution reached a breakpoint, the instruction there hasn't run yet
+    Breakpoint {
+        address: u16,
+    },
+    MemoryRead {
+        address: u16,
+        pc: u16,
+    },
+    MemoryWrite {
+        address: u16,
+        pc: u16,
+    },
+    RegisterChanged {
+        register: Register,
+        old: u16,
+        new: u16,
+        pc: u16,
+    },
+    Condition {
+        condition: Condition,
+        pc: u16,
+    },
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint { address } => write!(f, "breakpoint at {address:#05X}"),
+            StopReason::MemoryRead { address, pc } => {
+                write!(f, "{pc:#05X} read {address:#05X}")
+            }
+            StopReason::MemoryWrite { address, pc } => {
+                write!(f, "{pc:#05X} wrote {address:#05X}")
+            }
+            StopReason::RegisterChanged {
+                register,
+                old,
+                new,
+                pc,
+            } => write!(f, "{pc:#05X} changed {register} from {old:#X} to {new:#X}"),
+            StopReason::Condition { condition, pc } => {
+                write!(f, "{condition} after {pc:#05X}")
+            }
+        }
+    }
+}
+
+/// What the cpu looked like before an instruction, for comparing afterwards
+#[derive(Debug)]
+pub(crate) struct BeforeStep {
+    pc: u16,
+    /// The cpu isn't halted, so the step will run an instruction
+    executes: bool,
+    /// Values of the watched registers, in `register_watches` order
+    registers: Vec<u16>,
+    /// The ram the instruction is about to touch
+    access: Option<(Access, RangeInclusive<usize>)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    /// No instructions run while paused, `Emulator::run` keeps presenting and polling input
+    pub paused: bool,
+    /// Set by step over: pause once the cpu is back at this pc with this call depth
+    return_to: Option<(u16, usize)>,
+    breakpoints: BTreeSet<u16>,
+    memory_watches: Vec<MemoryWatch>,
+    register_watches: Vec<Register>,
+    /// Each condition with whether it held after the last instruction, they stop execution
+    /// when they turn true rather than on every instruction while true
+    conditions: Vec<(Condition, bool)>,
+    stop_reason: Option<StopReason>,
+    /// `stop_reason` hasn't been handed out by `take_new_stop` yet
+    unreported: bool,
+    /// Execution carried on and hasn't run an instruction since, so a breakpoint on the pc it
+    /// carried on from doesn't stop it again straight away
+    resumed: bool,
+}
+
+impl Debugger {
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.return_to = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.stop_reason = None;
+        self.unreported = false;
+        self.resumed = true;
+    }
+
+    /// Stop before executing the instruction at `address`
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn watch_memory(&mut self, watch: MemoryWatch) {
+        self.memory_watches.push(watch);
+    }
+
+    /// Stop after any instruction that changes `register`
+    pub fn watch_register(&mut self, register: Register) {
+        self.register_watches.push(register);
+    }
+
+    /// Stop after the instruction that makes `condition` true
+    pub fn break_if(&mut self, condition: Condition) {
+        self.conditions.push((condition, false));
+    }
+
+    /// Remove every breakpoint, watchpoint and condition
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.memory_watches.clear();
+        self.register_watches.clear();
+        self.conditions.clear();
+    }
+
+    /// Why execution last stopped, until it is resumed
+    pub fn stop_reason(&self) -> Option<&StopReason> {
+        self.stop_reason.as_ref()
+    }
+
+    /// The stop reason the first time it is asked for, so a frontend reports it once
+    pub fn take_new_stop(&mut self) -> Option<&StopReason> {
+        if !self.unreported {
+            return None;
+        }
+        self.unreported = false;
+        self.stop_reason.as_ref()
+    }
+
+    /// Run until the call at `cpu`'s pc has returned
+    pub(crate) fn run_until_return(&mut self, cpu: &Cpu) {
+        let after_call = cpu.program_counter.wrapping_add(2);
+        self.return_to = Some((after_call, cpu.stack_pointer));
+        self.paused = false;
+        self.resumed = true;
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        self.pause();
+        self.stop_reason = Some(reason);
+        self.unreported = true;
+    }
+
+    /// Called before each instruction, true if a breakpoint stops execution before it runs
+    pub(crate) fn at_breakpoint(&mut self, cpu: &Cpu) -> bool {
+        let address = cpu.program_counter;
+        if self.resumed || !self.breakpoints.contains(&address) {
+            return false;
+        }
+        self.stop(StopReason::Breakpoint { address });
+        true
+    }
+
+    /// Note what the instruction at the pc may change
+    pub(crate) fn before_step(&self, cpu: &Cpu) -> BeforeStep {
+        let executes = !(cpu.waiting_for_vblank || cpu.exited || cpu.key_wait.is_some());
+        let access = if executes && !self.memory_watches.is_empty() {
+            memory_access(cpu)
+        } else {
+            None
+        };
+        BeforeStep {
+            pc: cpu.program_counter,
+            executes,
+            registers: self.register_watches.iter().map(|r| r.read(cpu)).collect(),
+            access,
+        }
+    }
+
+    /// Called after each instruction, true if execution should stop here
+    pub(crate) fn should_stop(&mut self, cpu: &Cpu, before: BeforeStep) -> bool {
+        if self.return_to == Some((cpu.program_counter, cpu.stack_pointer)) {
+            self.pause();
+        }
+        // a halted cpu stays on its pc, so it's still sitting on the breakpoint it carried on from
+        if before.executes {
+            self.resumed = false;
+        }
+        let pc = before.pc;
+        self.check_watches(cpu, before);
+        let mut fired = None;
+        for (condition, held) in &mut self.conditions {
+            let holds = condition.holds(cpu);
+            if holds && !*held && fired.is_none() {
+                fired = Some(*condition);
+            }
+            *held = holds;
+        }
+        if let Some(condition) = fired {
+            self.stop(StopReason::Condition { condition, pc });
+        }
+        self.paused
+    }
+
+    fn check_watches(&mut self, cpu: &Cpu, before: BeforeStep) {
+        let pc = before.pc;
+        for (register, old) in self
+            .register_watches
+            .clone()
+            .into_iter()
+            .zip(before.registers)
+        {
+            let new = register.read(cpu);
+            if new != old {
+                return self.stop(StopReason::RegisterChanged {
+                    register,
+                    old,
+                    new,
+                    pc,
+                });
+            }
+        }
+        let Some((access, touched)) = before.access else {
+            return;
+        };
+        let hit = self.memory_watches.iter().find_map(|watch| {
+            let start = (*watch.addresses.start() as usize).max(*touched.start());
+            let end = (*watch.addresses.end() as usize).min(*touched.end());
+            (start <= end && watch.access.includes(access)).then_some(start as u16)
+        });
+        if let Some(address) = hit {
+            self.stop(match access {
+                Access::Write => StopReason::MemoryWrite { address, pc },
+                _ => StopReason::MemoryRead { address, pc },
+            });
+        }
+    }
+}
+
+/// The ram the instruction at the pc reads or writes through I
+fn memory_access(cpu: &Cpu) -> Option<(Access, RangeInclusive<usize>)> {
+    let pc = cpu.program_counter as usize;
+    let &[high, low] = cpu.memory.ram.get(pc..pc + 2)? else {
+        return None;
+    };
+    let (access, len) = match decode(OpCode(u16::from_be_bytes([high, low]))).ok()? {
+        Instruction::Draw { n, .. } => {
+            let sprite_len = if n == 0 { 32 } else { n as usize };
+            let planes = (cpu.memory.gpu.selected_planes & 3).count_ones() as usize;
+            (Access::Read, sprite_len * planes)
+        }
+        Instruction::StoreBcd { .. } => (Access::Write, 3),
+        Instruction::StoreRegs { x } => (Access::Write, x as usize + 1),
+        Instruction::LoadRegs { x } => (Access::Read, x as usize + 1),
+        Instruction::StoreRange { x, y } => (Access::Write, x.abs_diff(y) as usize + 1),
+        Instruction::LoadRange { x, y } => (Access::Read, x.abs_diff(y) as usize + 1),
+        _ => return None,
+    };
+    if len == 0 {
+        return None;
+    }
+    let start = cpu.index_register as usize;
+    Some((access, start..=start + len - 1))
+}
+
+#[cfg(test)]
+mod debuggertests {
+    use super::{Access, Comparison, Condition, MemoryWatch, Register, StopReason};
+    use crate::emu::Emulator;
+
+    fn emulator() -> Emulator {
+        let mut emu = Emulator::new();
+        let rom = [
+            0x22, 0x06, // CALL 0x206
+            0x71, 0x01, // ADD V1, 1
+            0x12, 0x02, // JP 0x202
+            0x72, 0x01, // ADD V2, 1
+            0x00, 0xEE, // RET
+        ];
+        emu.cpu.memory.ram[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        emu
+    }
+
+    #[test]
+    fn test_step_and_step_over() {
+        let mut emu = emulator();
+        emu.step_instruction().unwrap();
+        assert_eq!(emu.cpu.program_counter, 0x206);
+        assert!(emu.debugger.paused);
+
+        // paused, a frame runs nothing
+        emu.run_frame(10).unwrap();
+        assert_eq!(emu.cpu.program_counter, 0x206);
+
+        let mut emu = emulator();
+        emu.debugger.pause();
+        emu.step_over().unwrap();
+        assert!(!emu.debugger.paused);
+        emu.run_frame(10).unwrap();
+        // the whole subroutine ran, then it stopped right after the call
+        assert!(emu.debugger.paused);
+        assert_eq!(emu.cpu.program_counter, 0x202);
+        assert_eq!(emu.cpu.registers[2], 1);
+        assert_eq!(emu.cpu.registers[1], 0);
+
+        // not a call, so just one instruction
+        emu.step_over().unwrap();
+        assert_eq!(emu.cpu.program_counter, 0x204);
+        assert_eq!(emu.cpu.registers[1], 1);
+
+        emu.debugger.resume();
+        emu.run_frame(10).unwrap();
+        assert!(emu.cpu.registers[1] > 1);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut emu = emulator();
+        emu.debugger.add_breakpoint(0x206);
+        emu.run_frame(10).unwrap();
+        assert!(emu.debugger.paused);
+        assert_eq!(emu.cpu.program_counter, 0x206);
+        assert_eq!(
+            emu.debugger.stop_reason(),
+            Some(&StopReason::Breakpoint { address: 0x206 })
+        );
+        assert!(emu.debugger.take_new_stop().is_some());
+        assert!(emu.debugger.take_new_stop().is_none());
+
+        // carrying on runs the instruction under the breakpoint
+        emu.debugger.resume();
+        emu.run_frame(10).unwrap();
+        assert!(!emu.debugger.paused);
+        assert_eq!(emu.cpu.registers[2], 1);
+    }
+
+    #[test]
+    fn test_breakpoint_on_the_entry_point() {
+        let mut emu = emulator();
+        emu.debugger.add_breakpoint(0x200);
+        emu.run_frame(10).unwrap();
+        assert!(emu.debugger.paused);
+        assert_eq!(emu.cpu.program_counter, 0x200);
+        assert_eq!(
+            emu.debugger.stop_reason(),
+            Some(&StopReason::Breakpoint { address: 0x200 })
+        );
+
+        emu.debugger.resume();
+        emu.run_frame(10).unwrap();
+        assert!(!emu.debugger.paused);
+        assert_eq!(emu.cpu.registers[2], 1);
+    }
+
+    #[test]
+    fn test_memory_watchpoints() {
+        let mut emu = Emulator::new();
+        let rom = [
+            0xA3, 0x00, // LD I, 0x300
+            0x60, 0xFF, // LD V0, 0xFF
+            0xF0, 0x33, // LD B, V0
+            0xF2, 0x65, // LD V2, [I]
+            0x12, 0x08, // JP 0x208
+        ];
+        emu.cpu.memory.ram[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        emu.debugger.watch_memory("0x302-0x310:w".parse().unwrap());
+        emu.debugger.watch_memory("0x2FF-0x300:r".parse().unwrap());
+        emu.run_frame(10).unwrap();
+        assert_eq!(
+            emu.debugger.stop_reason(),
+            Some(&StopReason::MemoryWrite {
+                address: 0x302,
+                pc: 0x204
+            })
+        );
+        emu.debugger.resume();
+        emu.run_frame(10).unwrap();
+        assert_eq!(
+            emu.debugger.stop_reason(),
+            Some(&StopReason::MemoryRead {
+                address: 0x300,
+                pc: 0x206
+            })
+        );
+        assert_eq!(emu.cpu.registers[..3], [2, 5, 5]);
+    }
+
+    #[test]
+    fn test_register_watchpoints_and_conditions() {
+        let mut emu = Emulator::new();
+        // ADD V3, 8 forever
+        emu.cpu.memory.ram[0x200..0x204].copy_from_slice(&[0x73, 0x08, 0x12, 0x00]);
+        emu.debugger.break_if("V3 == 0x10".parse().unwrap());
+        emu.run_frame(10).unwrap();
+        assert_eq!(emu.cpu.registers[3], 0x10);
+        let reason = emu.debugger.stop_reason().unwrap();
+        assert_eq!(reason.to_string(), "V3 == 0x10 after 0x200");
+
+        emu.debugger.clear();
+        emu.debugger.resume();
+        emu.debugger.watch_register(Register::V(3));
+        emu.run_frame(10).unwrap();
+        assert_eq!(
+            emu.debugger.stop_reason(),
+            Some(&StopReason::RegisterChanged {
+                register: Register::V(3),
+                old: 0x10,
+                new: 0x18,
+                pc: 0x200
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_watches() {
+        let condition: Condition = "i>=512".parse().unwrap();
+        assert_eq!(condition.register, Register::I);
+        assert_eq!(condition.comparison, Comparison::GreaterOrEqual);
+        assert_eq!(condition.value, 512);
+        assert_eq!(condition.to_string(), "I >= 0x200");
+        assert!("V3 = 1".parse::<Condition>().is_err());
+        assert!("VG == 1".parse::<Condition>().is_err());
+
+        let watch: MemoryWatch = "0x300".parse().unwrap();
+        assert_eq!(watch.addresses, 0x300..=0x300);
+        assert_eq!(watch.access, Access::ReadWrite);
+        assert!("0x310-0x300".parse::<MemoryWatch>().is_err());
+        assert!("0x300:x".parse::<MemoryWatch>().is_err());
+    }
+}
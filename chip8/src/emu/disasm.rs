@@ -0,0 +1,279 @@
+// Turns rom bytes back into readable assembly.
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    instruction::{decode, Instruction},
+    iset::OpCode,
+};
+
+/// Where a disassembled rom would have been loaded, 0x200 for most programs.
+pub const DEFAULT_ORIGIN: u16 = 0x200;
+
+/// Reads the big-endian OpCode at `address`, if both of its bytes are inside the rom.
+pub(crate) fn opcode_at(rom: &[u8], origin: u16, address: u16) -> Option<OpCode> {
+    let offset = address.checked_sub(origin)? as usize;
+    let bytes = rom.get(offset..offset + 2)?;
+    Some(OpCode(u16::from_be_bytes([bytes[0], bytes[1]])))
+}
+
+/// Follows every jump, call and skip reachable from `origin`, returning the address of each
+/// instruction that can execute. Everything else in the rom is treated as sprite/data bytes.
+/// Bnnn jumps depend on V0 at runtime so their targets can't be followed.
+pub fn trace_code(rom: &[u8], origin: u16) -> BTreeSet<u16> {
+    trace_reachable(rom, origin).code
+}
+
+/// What the program can run into, found by `trace_reachable`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reachable {
+    /// Address of each instruction that can execute
+    pub code: BTreeSet<u16>,
+    /// Addresses inside the rom that execution can reach but that don't hold an instruction
+    pub unknown: BTreeSet<u16>,
+}
+
+/// `trace_code`, also keeping the reachable addresses whose opcode doesn't decode
+pub fn trace_reachable(rom: &[u8], origin: u16) -> Reachable {
+    let mut code = BTreeSet::new();
+    let mut unknown = BTreeSet::new();
+    let mut pending = vec![origin];
+    while let Some(address) = pending.pop() {
+        if code.contains(&address) {
+            continue;
+        }
+        let Some(opcode) = opcode_at(rom, origin, address) else {
+            continue; // ran off the rom
+        };
+        let Ok(instruction) = decode(opcode) else {
+            unknown.insert(address);
+            continue;
+        };
+        code.insert(address);
+        let next = address.wrapping_add(instruction.size());
+        // skips jump over the whole next instruction, which is 4 bytes if it's an F000
+        let after_next = match opcode_at(rom, origin, next) {
+            Some(OpCode(0xF000)) => next.wrapping_add(4),
+            _ => next.wrapping_add(2),
+        };
+        match instruction {
+            // there's no machine code to run, so 0nnn jumps like 1nnn does
+            Instruction::Jump { nnn } | Instruction::Sys { nnn } => pending.push(nnn),
+            Instruction::Call { nnn } => pending.extend([nnn, next]),
+            Instruction::Ret | Instruction::JumpOffset { .. } => {}
+            Instruction::SkipEqImm { .. }
+            | Instruction::SkipNeImm { .. }
+            | Instruction::SkipEqReg { .. }
+            | Instruction::SkipNeReg { .. }
+            | Instruction::SkipKey { .. }
+            | Instruction::SkipNotKey { .. } => pending.extend([next, after_next]),
+            _ => pending.push(next),
+        }
+    }
+    Reachable { code, unknown }
+}
+
+/// Names every jump and call target found in `code`, calls as `sub_XXX` and jumps as `label_XXX`.
+pub fn find_labels(rom: &[u8], origin: u16, code: &BTreeSet<u16>) -> BTreeMap<u16, String> {
+    let mut labels = BTreeMap::new();
+    for &address in code {
+        match opcode_at(rom, origin, address).and_then(|op| decode(op).ok()) {
+            Some(Instruction::Call { nnn }) => {
+                labels.insert(nnn, format!("sub_{nnn:03X}"));
+            }
+            Some(Instruction::Jump { nnn } | Instruction::Sys { nnn }) => {
+                labels
+                    .entry(nnn)
+                    .or_insert_with(|| format!("label_{nnn:03X}"));
+            }
+            _ => {}
+        }
+    }
+    labels
+}
+
+/// Renders a sprite byte as pixels, e.g. 0xF0 => `####....`
+fn sprite_row(byte: u8) -> String {
+    (0..8)
+        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+        .collect()
+}
+
+/// Disassemble a rom that is loaded at `origin`.
+/// Code is written one instruction per line with the address and raw opcode as a comment,
+/// jump/call targets get labels and data bytes become `db` lines drawn as sprite rows.
+pub fn disassemble(rom: &[u8], origin: u16) -> Vec<String> {
+    let code = trace_code(rom, origin);
+    let labels = find_labels(rom, origin, &code);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = origin + offset as u16;
+        if let Some(label) = labels.get(&address) {
+            lines.push(format!("{label}:"));
+        }
+        let opcode = opcode_at(rom, origin, address);
+        match opcode.filter(|_| code.contains(&address)).map(decode) {
+            Some(Ok(instruction)) => {
+                let target = match instruction {
+                    Instruction::Jump { nnn }
+                    | Instruction::Sys { nnn }
+                    | Instruction::Call { nnn } => labels.get(&nnn),
+                    _ => None,
+                };
+                let mut text = match (instruction, target) {
+                    (Instruction::Jump { .. }, Some(label)) => format!("JP {label}"),
+                    (Instruction::Call { .. }, Some(label)) => format!("CALL {label}"),
+                    (Instruction::Sys { .. }, Some(label)) => format!("SYS {label}"),
+                    _ => instruction.to_string(),
+                };
+                let mut raw = format!("{:04X}", opcode.map_or(0, |op| op.0));
+                let mut size = 2;
+                // F000 carries its address in the following word
+                if instruction == Instruction::LongIndex {
+                    if let Some(OpCode(nnnn)) = opcode_at(rom, origin, address + 2) {
+                        text = format!("LD I, LONG 0x{nnnn:04X}");
+                        raw = format!("{raw} {nnnn:04X}");
+                        size = 4;
+                    }
+                }
+                lines.push(format!("    {text:<24}; {address:03X}: {raw}"));
+                offset += size;
+            }
+            _ => {
+                let byte = rom[offset];
+                let art = sprite_row(byte);
+                lines.push(format!(
+                    "    {:<24}; {address:03X}: {art}",
+                    format!("db 0x{byte:02X}")
+                ));
+                offset += 1;
+            }
+        }
+    }
+    lines
+}
+
+/// The instruction at `address` of live memory, for the debugger: its text and size in bytes.
+/// Anything that doesn't decode is shown as a `db` of its first byte.
+pub fn instruction_at(ram: &[u8], address: u16) -> (String, u16) {
+    let Some(opcode) = opcode_at(ram, 0, address) else {
+        let byte = ram.get(address as usize).copied().unwrap_or(0);
+        return (format!("db 0x{byte:02X}"), 1);
+    };
+    match decode(opcode) {
+        Ok(Instruction::LongIndex) => match opcode_at(ram, 0, address.wrapping_add(2)) {
+            Some(OpCode(nnnn)) => (format!("LD I, LONG 0x{nnnn:04X}"), 4),
+            None => (Instruction::LongIndex.to_string(), 2),
+        },
+        Ok(instruction) => (instruction.to_string(), 2),
+        Err(_) => (format!("db 0x{:02X}", opcode.0 >> 8), 1),
+    }
+}
+
+#[cfg(test)]
+mod disasmtests {
+    use super::{disassemble, instruction_at, trace_code, DEFAULT_ORIGIN};
+
+    const MAZE: &[u8] = &[
+        0xA2, 0x1E, 0xC2, 0x01, 0x32, 0x01, 0xA2, 0x1A, 0xD0, 0x14, 0x70, 0x04, 0x30, 0x40, 0x12,
+        0x00, 0x60, 0x00, 0x71, 0x04, 0x31, 0x20, 0x12, 0x00, 0x12, 0x18, 0x80, 0x40, 0x20, 0x10,
+        0x20, 0x40, 0x80, 0x10,
+    ];
+
+    #[test]
+    fn test_trace_code_stops_at_data() {
+        let code = trace_code(MAZE, DEFAULT_ORIGIN);
+        // 0x200..=0x218 is code, the sprites after the halting jump are not,
+        // even though 0x21C (2010) would decode as CALL 0x010
+        let expected: Vec<u16> = (0x200..=0x218).step_by(2).collect();
+        assert_eq!(code.into_iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_disassemble_labels_and_data() {
+        let lines = disassemble(MAZE, DEFAULT_ORIGIN);
+        assert_eq!(lines[0], "label_200:");
+        assert_eq!(lines[1], "    LD I, 0x21E             ; 200: A21E");
+        assert!(lines.contains(&"    JP label_200            ; 20E: 1200".to_string()));
+        assert!(lines.contains(&"label_218:".to_string()));
+        assert!(lines.contains(&"    JP label_218            ; 218: 1218".to_string()));
+        assert_eq!(
+            lines.last().unwrap(),
+            "    db 0x10                 ; 221: ...#...."
+        );
+        // 8 data bytes after the 13 instructions and 2 labels
+        assert_eq!(lines.len(), 2 + 13 + 8);
+    }
+
+    #[test]
+    fn test_disassemble_long_index() {
+        let rom = [
+            0x30, 0x00, // 200: SE V0, 0x00
+            0xF0, 0x00, 0x12, 0x34, // 202: LD I, LONG 0x1234
+            0x00, 0xFD, // 206: EXIT
+        ];
+        let lines = disassemble(&rom, DEFAULT_ORIGIN);
+        assert_eq!(
+            lines,
+            [
+                "    SE V0, 0x00             ; 200: 3000",
+                "    LD I, LONG 0x1234       ; 202: F000 1234",
+                "    EXIT                    ; 206: 00FD",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_follows_calls() {
+        let rom = [
+            0x22, 0x06, // 200: CALL 0x206
+            0x12, 0x02, // 202: JP 0x202
+            0xFF, 0xFF, // 204: data
+            0x00, 0xEE, // 206: RET
+        ];
+        let lines = disassemble(&rom, DEFAULT_ORIGIN);
+        assert_eq!(
+            lines,
+            [
+                "    CALL sub_206            ; 200: 2206",
+                "label_202:",
+                "    JP label_202            ; 202: 1202",
+                "    db 0xFF                 ; 204: ########",
+                "    db 0xFF                 ; 205: ########",
+                "sub_206:",
+                "    RET                     ; 206: 00EE",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_follows_sys_like_a_jump() {
+        let rom = [
+            0x02, 0x04, // 200: SYS 0x204
+            0xFF, 0xFF, // 202: data
+            0x12, 0x04, // 204: JP 0x204
+        ];
+        let lines = disassemble(&rom, DEFAULT_ORIGIN);
+        assert_eq!(
+            lines,
+            [
+                "    SYS label_204           ; 200: 0204",
+                "    db 0xFF                 ; 202: ########",
+                "    db 0xFF                 ; 203: ########",
+                "label_204:",
+                "    JP label_204            ; 204: 1204",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instruction_at() {
+        let ram = [0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34, 0xFF];
+        assert_eq!(instruction_at(&ram, 0), ("CLS".to_string(), 2));
+        assert_eq!(
+            instruction_at(&ram, 2),
+            ("LD I, LONG 0x1234".to_string(), 4)
+        );
+        assert_eq!(instruction_at(&ram, 6), ("db 0xFF".to_string(), 1));
+    }
+}
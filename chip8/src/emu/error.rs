@@ -0,0 +1,69 @@
+// Everything that can go wrong while emulating. A misbehaving rom ends the run with one of
+// these rather than a panic, so the report says what the program did and where.
+use std::{fmt, io};
+
+use super::cpu::STACK_SIZE;
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// The opcode at `pc` isn't an instruction on any supported platform
+    InvalidOpcode { opcode: u16, pc: u16 },
+    /// A call at `pc` would nest deeper than STACK_SIZE
+    StackOverflow { pc: u16 },
+    /// A return at `pc` with no call to return to
+    StackUnderflow { pc: u16 },
+    /// The program counter left memory
+    PcOutOfBounds { pc: u16 },
+    /// The instruction at `pc` accesses `len` bytes from I = `index`, past the end of memory
+    IndexOutOfBounds { index: u16, len: usize, pc: u16 },
+    /// The rom doesn't fit between its start address and the end of memory
+    RomTooLarge { size: usize, max: usize },
+    /// A rewind snapshot didn't load back, `save_state` wrote something `load_state` refused
+    BadSnapshot(String),
+    /// Talking to the host failed: the terminal, or a file the run writes such as a trace
+    Frontend(io::Error),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::InvalidOpcode { opcode, pc } => {
+                write!(f, "unknown opcode {opcode:04X} at {pc:#05X}")
+            }
+            Chip8Error::StackOverflow { pc } => write!(
+                f,
+                "stack overflow: call depth exceeds {STACK_SIZE} at {pc:#05X}"
+            ),
+            Chip8Error::StackUnderflow { pc } => {
+                write!(f, "stack underflow: return with an empty stack at {pc:#05X}")
+            }
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "program counter {pc:#05X} is outside of memory")
+            }
+            Chip8Error::IndexOutOfBounds { index, len, pc } => write!(
+                f,
+                "instruction at {pc:#05X} accesses {len} bytes from I = {index:#05X}, past the end of memory"
+            ),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "rom is {size} bytes, only {max} fit in memory")
+            }
+            Chip8Error::BadSnapshot(reason) => write!(f, "rewind snapshot didn't load: {reason}"),
+            Chip8Error::Frontend(err) => write!(f, "frontend I/O failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chip8Error::Frontend(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(err: io::Error) -> Self {
+        Chip8Error::Frontend(err)
+    }
+}
@@ -0,0 +1,182 @@
+// How the emulator talks to whatever hosts it: a terminal, a test, or something else entirely.
+use std::time::Duration;
+
+use super::{error::Chip8Error, input::Keypad, Emulator};
+
+pub mod headless;
+pub mod tui;
+
+/// Requests from the host that aren't chip8 key presses
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Stop emulating
+    Quit,
+    /// Save the machine to a numbered quick-save slot
+    SaveSlot(u8),
+    /// Restore the machine from a numbered quick-save slot
+    LoadSlot(u8),
+    /// Step back through recent frames instead of running the next one, sent every
+    /// frame the rewind key is held
+    Rewind,
+    /// Stop running instructions, frames keep being presented
+    Pause,
+    /// Carry on after a pause
+    Continue,
+    /// Pause and execute a single instruction
+    Step,
+    /// Step, but run a subroutine call through to its return
+    StepOver,
+}
+
+/// A host for the emulator. `Emulator::run` calls `present` after every frame,
+/// then hands the rest of the frame to `poll_input`.
+pub trait Frontend {
+    /// Show the frame that just finished
+    fn present(&mut self, emu: &Emulator) -> Result<(), Chip8Error>;
+
+    /// Update `pad` with the input arriving within `timeout` and return any host commands.
+    /// Waiting out the timeout is what paces emulation to 60 frames a second.
+    fn poll_input(
+        &mut self,
+        pad: &mut Keypad,
+        timeout: Duration,
+    ) -> Result<Vec<Command>, Chip8Error>;
+
+    /// Tell the user how a command went, e.g. that a slot was saved
+    fn notify(&mut self, _message: &str) {}
+}
+
+#[cfg(test)]
+mod frontendtests {
+    use super::{Command, Frontend};
+    use crate::emu::{error::Chip8Error, input::Keypad, rewind::Rewind, Emulator};
+    use std::time::Duration;
+
+    type Result<T> = std::result::Result<T, Chip8Error>;
+
+    /// Holds key 5 for the first frames, then quits
+    struct Scripted {
+        presented: usize,
+    }
+
+    impl Frontend for Scripted {
+        fn present(&mut self, _emu: &Emulator) -> Result<()> {
+            self.presented += 1;
+            Ok(())
+        }
+
+        fn poll_input(&mut self, pad: &mut Keypad, _timeout: Duration) -> Result<Vec<Command>> {
+            match self.presented {
+                1 => pad.press(5),
+                2 => pad.release(5),
+                4 => return Ok(vec![Command::Quit]),
+                _ => {}
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn test_run_without_a_terminal() {
+        let mut emu = Emulator::new();
+        // LD V0, K; then count frames in V1
+        for (i, byte) in [0xF0, 0x0A, 0x71, 0x01, 0x12, 0x02].into_iter().enumerate() {
+            emu.cpu.memory.ram[0x200 + i] = byte;
+        }
+        let mut frontend = Scripted { presented: 0 };
+        emu.run(&mut frontend, 1, Duration::ZERO).unwrap();
+        assert_eq!(frontend.presented, 4);
+        assert_eq!(emu.cpu.registers[0], 5);
+    }
+
+    #[test]
+    fn test_run_stops_at_exit() {
+        let mut emu = Emulator::new();
+        emu.cpu.memory.ram[0x200..0x202].copy_from_slice(&[0x00, 0xFD]);
+        // past the scripted frames, only 00FD can stop it
+        let mut frontend = Scripted { presented: 10 };
+        emu.run(&mut frontend, 9, Duration::ZERO).unwrap();
+        assert_eq!(frontend.presented, 11);
+    }
+
+    /// Saves slot 1 after the first frame, loads it after the third, then quits
+    #[derive(Default)]
+    struct QuickSaver {
+        presented: usize,
+        messages: Vec<String>,
+    }
+
+    impl Frontend for QuickSaver {
+        fn present(&mut self, _emu: &Emulator) -> Result<()> {
+            self.presented += 1;
+            Ok(())
+        }
+
+        fn poll_input(&mut self, _pad: &mut Keypad, _timeout: Duration) -> Result<Vec<Command>> {
+            Ok(match self.presented {
+                1 => vec![Command::SaveSlot(1), Command::LoadSlot(2)],
+                3 => vec![Command::LoadSlot(1), Command::Quit],
+                _ => Vec::new(),
+            })
+        }
+
+        fn notify(&mut self, message: &str) {
+            self.messages.push(message.to_string());
+        }
+    }
+
+    #[test]
+    fn test_quick_save_slots() {
+        let mut emu = Emulator::new();
+        emu.state_dir = std::env::temp_dir().join(format!("chip8-slots-{}", std::process::id()));
+        // count frames in V1
+        emu.cpu.memory.ram[0x200..0x204].copy_from_slice(&[0x71, 0x01, 0x12, 0x00]);
+        let mut frontend = QuickSaver::default();
+        emu.run(&mut frontend, 1, Duration::ZERO).unwrap();
+        // back to how it was after the first frame
+        assert_eq!(emu.cpu.registers[1], 1);
+        assert_eq!(frontend.messages[0], "Saved slot 1");
+        // slot 2 was never saved, which is reported rather than fatal
+        assert!(frontend.messages[1].starts_with("Load failed"));
+        assert_eq!(frontend.messages[2], "Loaded slot 1");
+        std::fs::remove_dir_all(&emu.state_dir).unwrap();
+    }
+
+    /// Holds key 2 and rewinds for two frames after the fourth
+    struct Rewinder {
+        presented: usize,
+    }
+
+    impl Frontend for Rewinder {
+        fn present(&mut self, _emu: &Emulator) -> Result<()> {
+            self.presented += 1;
+            Ok(())
+        }
+
+        fn poll_input(&mut self, pad: &mut Keypad, _timeout: Duration) -> Result<Vec<Command>> {
+            Ok(match self.presented {
+                4 => {
+                    pad.press(2);
+                    vec![Command::Rewind]
+                }
+                5 => vec![Command::Rewind],
+                6 => vec![Command::Quit],
+                _ => Vec::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_rewind_steps_back_a_frame_at_a_time() {
+        let mut emu = Emulator::new();
+        emu.rewind = Rewind::new(1, 64 * 1024);
+        // count frames in V1
+        emu.cpu.memory.ram[0x200..0x204].copy_from_slice(&[0x71, 0x01, 0x12, 0x00]);
+        let mut frontend = Rewinder { presented: 0 };
+        emu.run(&mut frontend, 2, Duration::ZERO).unwrap();
+        assert_eq!(frontend.presented, 6);
+        assert_eq!(emu.cpu.registers[1], 2);
+        // the keys held now survive going back
+        assert!(emu.cpu.memory.pad.is_pressed(2));
+    }
+}
@@ -0,0 +1,241 @@
+// Runs without a terminal for CI and regression checks, saving frames as images instead.
+use std::{
+    collections::BTreeSet,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use super::{Command, Frontend};
+use crate::emu::{error::Chip8Error, gpu::Gpu, input::Keypad, Emulator};
+
+/// How dumped frames are written
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Plain text netpbm bitmap, easy to diff. Lit pixels are `1`, which netpbm shows as black.
+    Pbm,
+    /// 1-bit greyscale PNG with lit pixels white
+    Png,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 2] = [ImageFormat::Pbm, ImageFormat::Png];
+
+    /// The file extension and command line name
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageFormat::Pbm => "pbm",
+            ImageFormat::Png => "png",
+        }
+    }
+
+    pub fn write(self, gpu: &Gpu, writer: impl Write) -> io::Result<()> {
+        match self {
+            ImageFormat::Pbm => write_pbm(gpu, writer)?,
+            ImageFormat::Png => write_png(gpu, writer)?,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        ImageFormat::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown image format `{name}`, expected pbm or png"))
+    }
+}
+
+/// Writes `gpu.screen` as a plain PBM, one line of `0`s and `1`s per row
+pub fn write_pbm(gpu: &Gpu, mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "P1")?;
+    writeln!(writer, "{} {}", gpu.width(), gpu.height())?;
+    for row in gpu.screen.chunks(gpu.width()) {
+        let line: String = row.iter().map(|&on| if on { '1' } else { '0' }).collect();
+        writeln!(writer, "{line}")?;
+    }
+    Ok(())
+}
+
+/// Writes `gpu.screen` as a 1-bit greyscale PNG
+pub fn write_png(gpu: &Gpu, writer: impl Write) -> Result<(), png::EncodingError> {
+    let (width, height) = (gpu.width(), gpu.height());
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+    // rows are packed 8 pixels to a byte, most significant bit first
+    let mut data = Vec::with_capacity(height * width.div_ceil(8));
+    for row in gpu.screen.chunks(width) {
+        for pixels in row.chunks(8) {
+            let byte = pixels
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (bit, &on)| byte | (on as u8) << (7 - bit));
+            data.push(byte);
+        }
+    }
+    encoder.write_header()?.write_image_data(&data)
+}
+
+/// Runs a fixed number of frames as fast as possible and dumps the framebuffer to `dump_dir`
+#[derive(Debug)]
+pub struct HeadlessFrontend {
+    frames: u64,
+    presented: u64,
+    dump_dir: Option<PathBuf>,
+    format: ImageFormat,
+    /// Frame numbers to dump, counting from 1. Empty dumps only the final frame.
+    dump_at: BTreeSet<u64>,
+}
+
+impl HeadlessFrontend {
+    /// Quit after `frames` frames without saving anything
+    pub fn new(frames: u64) -> Self {
+        Self {
+            frames,
+            presented: 0,
+            dump_dir: None,
+            format: ImageFormat::Pbm,
+            dump_at: BTreeSet::new(),
+        }
+    }
+
+    /// Save frames into `dir`, the ones listed in `at` or just the last if it is empty
+    pub fn dump_to(mut self, dir: impl Into<PathBuf>, format: ImageFormat, at: &[u64]) -> Self {
+        self.dump_dir = Some(dir.into());
+        self.format = format;
+        self.dump_at = at.iter().copied().collect();
+        self
+    }
+
+    /// Frames presented so far
+    pub fn presented(&self) -> u64 {
+        self.presented
+    }
+
+    /// Frames in the `dump_to` list the run ended before, so they were never written
+    pub fn missed(&self) -> Vec<u64> {
+        let after = self.presented.saturating_add(1);
+        self.dump_at.range(after..).copied().collect()
+    }
+
+    /// Call once the run is over: in final-frame-only mode this dumps the last frame,
+    /// which is also what is on screen if the program exited early.
+    pub fn finish(&mut self, emu: &Emulator) -> Result<(), Chip8Error> {
+        if self.dump_at.is_empty() {
+            self.dump(emu, self.presented)?;
+        }
+        Ok(())
+    }
+
+    fn dump(&self, emu: &Emulator, frame: u64) -> Result<(), Chip8Error> {
+        let Some(dir) = &self.dump_dir else {
+            return Ok(());
+        };
+        let path = dir.join(format!("frame_{frame:06}.{}", self.format));
+        let write = || -> io::Result<()> {
+            fs::create_dir_all(dir)?;
+            let mut writer = BufWriter::new(File::create(&path)?);
+            self.format.write(&emu.cpu.memory.gpu, &mut writer)?;
+            writer.flush()
+        };
+        // io errors don't say which file they were about
+        write().map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+        Ok(())
+    }
+}
+
+impl Frontend for HeadlessFrontend {
+    fn present(&mut self, emu: &Emulator) -> Result<(), Chip8Error> {
+        self.presented += 1;
+        if self.dump_at.contains(&self.presented) {
+            self.dump(emu, self.presented)?;
+        }
+        Ok(())
+    }
+
+    /// Nobody is typing, and there is no reason to wait out the frame
+    fn poll_input(
+        &mut self,
+        _pad: &mut Keypad,
+        _timeout: Duration,
+    ) -> Result<Vec<Command>, Chip8Error> {
+        if self.presented >= self.frames {
+            return Ok(vec![Command::Quit]);
+        }
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod headlesstests {
+    use super::{write_pbm, write_png, HeadlessFrontend, ImageFormat};
+    use crate::emu::{gpu::Gpu, Emulator};
+    use std::time::Duration;
+
+    #[test]
+    fn test_write_pbm() {
+        let mut gpu = Gpu::new();
+        gpu.screen[1] = true;
+        gpu.screen[64 + 63] = true;
+        let mut out = Vec::new();
+        write_pbm(&gpu, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "P1");
+        assert_eq!(lines[1], "64 32");
+        assert_eq!(lines.len(), 2 + 32);
+        assert_eq!(lines[2], format!("01{}", "0".repeat(62)));
+        assert_eq!(lines[3], format!("{}1", "0".repeat(63)));
+    }
+
+    #[test]
+    fn test_write_png() {
+        let mut gpu = Gpu::new();
+        gpu.set_hires(true);
+        gpu.screen[0] = true;
+        let mut out = Vec::new();
+        write_png(&gpu, &mut out).unwrap();
+        assert_eq!(&out[1..4], b"PNG");
+
+        let decoder = png::Decoder::new(out.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (128, 64));
+        assert_eq!(data[0], 0x80);
+        assert!(data[1..info.buffer_size()].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_headless_dumps_chosen_frames() {
+        let dir = std::env::temp_dir().join(format!("chip8-headless-{}", std::process::id()));
+        let mut emu = Emulator::new();
+        emu.cpu.memory.ram[0x200..0x202].copy_from_slice(&[0x12, 0x00]); // spin
+        let mut frontend = HeadlessFrontend::new(5).dump_to(&dir, ImageFormat::Pbm, &[2, 4, 7]);
+        emu.run(&mut frontend, 9, Duration::from_secs(60)).unwrap();
+        frontend.finish(&emu).unwrap();
+        assert_eq!(frontend.presented(), 5);
+        assert_eq!(frontend.missed(), [7]);
+
+        let mut files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["frame_000002.pbm", "frame_000004.pbm"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,639 @@
+// The terminal frontend, drawing the framebuffer with ratatui and reading keys with crossterm.
+
+use ratatui::{
+    backend::CrosstermBackend,
+    buffer::Buffer,
+    crossterm::event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    crossterm::{
+        execute,
+        terminal::{
+            disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+            LeaveAlternateScreen,
+        },
+    },
+    layout::{Alignment, Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    symbols::{border, Marker},
+    text::{Line as TextLine, Span},
+    widgets::{
+        block::{Position, Title},
+        canvas::{Canvas, Rectangle},
+        Block, Paragraph, Widget,
+    },
+    Terminal,
+};
+
+use std::{
+    io::{self, stdout, Stdout},
+    time::{Duration, Instant},
+};
+
+use super::{Command, Frontend};
+use crate::emu::{
+    disasm::instruction_at,
+    error::Chip8Error,
+    gpu::Gpu,
+    input::{Keypad, KEY_COUNT},
+    romdb::RomInfo,
+    Emulator,
+};
+
+/// A type alias for the terminal type used in this application
+pub type Tui = Terminal<CrosstermBackend<Stdout>>;
+
+/// Most terminals never report key releases, so a key counts as held for this many frames
+/// after its last press or auto-repeat.
+const KEY_HOLD_FRAMES: u8 = 10;
+/// F1-F4 save to slots 1-4 and F5-F8 load them back
+const SLOTS: u8 = 4;
+/// How long a message from `notify` stays in the bottom border
+const STATUS_DURATION: Duration = Duration::from_secs(2);
+/// Bytes per row of the debugger's memory view
+const MEMORY_ROW: usize = 8;
+/// Instructions the disassembly shows before the pc
+const DISASSEMBLY_BEFORE: u16 = 4;
+
+pub struct TuiFrontend {
+    terminal: Tui,
+    /// The terminal reports key releases, so keys don't need KEY_HOLD_FRAMES to let go
+    key_releases: bool,
+    /// Frames left before each key is released without a release event
+    key_hold: [u8; KEY_COUNT],
+    /// Frames left of rewinding, the rewind key is held the same way as the chip8 keys
+    rewind_hold: u8,
+    /// Last message from `notify` and when it arrived
+    status: Option<(String, Instant)>,
+    /// Show the debugger panel beside the screen, toggled with Tab
+    debug: bool,
+    /// First row of the memory view, None follows the pc
+    memory_scroll: Option<usize>,
+    /// Row of the memory view holding the pc at the last frame, where scrolling starts from
+    pc_row: usize,
+    /// From the rom database, the title replaces the default border title
+    title: Option<String>,
+    /// From the rom database, what the rom's keys do
+    key_hints: Option<String>,
+}
+
+impl TuiFrontend {
+    /// Initialize the terminal
+    pub fn init() -> io::Result<Self> {
+        execute!(stdout(), EnterAlternateScreen)?;
+        // What is raw_mode?
+        //   Starts taking input immediately w/o waiting for newline
+        //   and prevents typed keys being echo'd back
+        enable_raw_mode()?;
+        // terminals speaking the kitty keyboard protocol can tell us when keys go up
+        let key_releases = supports_keyboard_enhancement().unwrap_or(false);
+        if key_releases {
+            execute!(
+                stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        set_panic_hook(key_releases);
+        Ok(Self {
+            terminal: Terminal::new(CrosstermBackend::new(stdout()))?,
+            key_releases,
+            key_hold: [0; KEY_COUNT],
+            rewind_hold: 0,
+            status: None,
+            debug: false,
+            memory_scroll: None,
+            pc_row: 0,
+            title: None,
+            key_hints: None,
+        })
+    }
+
+    /// Show the title and key hints the rom database has for the rom
+    pub fn with_rom(mut self, info: &RomInfo) -> Self {
+        self.title = Some(info.title.clone());
+        self.key_hints = info.keys.clone();
+        self
+    }
+
+    /// Restore the terminal to its original state
+    pub fn restore(&mut self) -> io::Result<()> {
+        restore_terminal(self.key_releases)
+    }
+
+    /// Release keys whose hold ran out, for terminals without release events
+    fn expire_held_keys(&mut self, pad: &mut Keypad) {
+        for (key, frames) in self.key_hold.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    pad.release(key as u8);
+                }
+            }
+        }
+    }
+
+    /// Update `pad` if the key is one of the 16 chip8 keys
+    fn handle_key_event(&mut self, key_event: KeyEvent, pad: &mut Keypad) -> Option<Command> {
+        if key_event.kind == KeyEventKind::Release {
+            if let Some(key) = chip8_key(key_event.code) {
+                pad.release(key);
+            }
+            if key_event.code == KeyCode::Backspace {
+                self.rewind_hold = 0;
+            }
+            return None;
+        }
+        if self.debug {
+            if let Some(command) = self.handle_debug_key(key_event.code) {
+                return command;
+            }
+        }
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('0') => Some(Command::Quit),
+            KeyCode::Backspace => {
+                // with release events it is held until let go
+                self.rewind_hold = if self.key_releases {
+                    u8::MAX
+                } else {
+                    KEY_HOLD_FRAMES
+                };
+                None
+            }
+            KeyCode::F(n) => slot_command(n),
+            KeyCode::Tab => {
+                self.debug = !self.debug;
+                None
+            }
+            code => {
+                let key = chip8_key(code)?;
+                pad.press(key);
+                if !self.key_releases {
+                    self.key_hold[key as usize] = KEY_HOLD_FRAMES;
+                }
+                None
+            }
+        }
+    }
+}
+
+impl TuiFrontend {
+    /// The debugger panel's keys, none of which are on the chip8 keypad.
+    /// Returns None for keys the debugger doesn't use.
+    fn handle_debug_key(&mut self, code: KeyCode) -> Option<Option<Command>> {
+        let page = 8;
+        let command = match code {
+            KeyCode::Char('p') => Some(Command::Pause),
+            KeyCode::Char('g') => Some(Command::Continue),
+            KeyCode::Char('i') => Some(Command::Step),
+            KeyCode::Char('o') => Some(Command::StepOver),
+            KeyCode::PageUp => {
+                let row = self.memory_scroll.unwrap_or(self.pc_row);
+                self.memory_scroll = Some(row.saturating_sub(page));
+                None
+            }
+            KeyCode::PageDown => {
+                let row = self.memory_scroll.unwrap_or(self.pc_row);
+                self.memory_scroll = Some(row + page);
+                None
+            }
+            KeyCode::Home => {
+                self.memory_scroll = None;
+                None
+            }
+            _ => return None,
+        };
+        Some(command)
+    }
+}
+
+impl Frontend for TuiFrontend {
+    fn present(&mut self, emu: &Emulator) -> Result<(), Chip8Error> {
+        if let Some((_, since)) = &self.status {
+            if since.elapsed() > STATUS_DURATION {
+                self.status = None;
+            }
+        }
+        let screen = Screen {
+            gpu: &emu.cpu.memory.gpu,
+            status: self.status.as_ref().map(|(message, _)| message.as_str()),
+            title: self.title.as_deref(),
+            key_hints: self.key_hints.as_deref(),
+        };
+        if !self.debug {
+            self.terminal
+                .draw(|frame| frame.render_widget(screen, frame.area()))?;
+            return Ok(());
+        }
+        self.pc_row = emu.cpu.program_counter as usize / MEMORY_ROW;
+        let panel = DebugPanel {
+            emu,
+            memory_scroll: self.memory_scroll,
+        };
+        self.terminal.draw(|frame| {
+            let [screen_area, panel_area] =
+                Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                    .areas(frame.area());
+            frame.render_widget(screen, screen_area);
+            frame.render_widget(panel, panel_area);
+        })?;
+        Ok(())
+    }
+
+    fn poll_input(
+        &mut self,
+        pad: &mut Keypad,
+        timeout: Duration,
+    ) -> Result<Vec<Command>, Chip8Error> {
+        // keep polling until the frame is over, several keys can change in one frame
+        self.expire_held_keys(pad);
+        let mut commands = Vec::new();
+        let deadline = Instant::now() + timeout;
+        while event::poll(deadline.saturating_duration_since(Instant::now()))? {
+            if let Event::Key(key_event) = event::read()? {
+                commands.extend(self.handle_key_event(key_event, pad));
+            }
+        }
+        if self.rewind_hold > 0 {
+            if !self.key_releases {
+                self.rewind_hold -= 1;
+            }
+            commands.push(Command::Rewind);
+        }
+        Ok(commands)
+    }
+
+    fn notify(&mut self, message: &str) {
+        self.status = Some((message.to_string(), Instant::now()));
+    }
+}
+
+fn restore_terminal(key_releases: bool) -> io::Result<()> {
+    if key_releases {
+        execute!(stdout(), PopKeyboardEnhancementFlags)?;
+    }
+    execute!(stdout(), LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+    Ok(())
+}
+
+fn set_panic_hook(key_releases: bool) {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        let _ = restore_terminal(key_releases);
+        hook(panic_info);
+    }))
+}
+
+/// The 16 chip8 keys laid out as the 4x4 block 1234/qwer/asdf/zxcv
+fn chip8_key(code: KeyCode) -> Option<u8> {
+    let KeyCode::Char(c) = code else {
+        return None;
+    };
+    "1234qwerasdfzxcv".find(c).map(|key| key as u8)
+}
+
+/// The quick-save command for function key `n`
+fn slot_command(n: u8) -> Option<Command> {
+    match n {
+        1..=SLOTS => Some(Command::SaveSlot(n)),
+        n if n > SLOTS && n <= SLOTS * 2 => Some(Command::LoadSlot(n - SLOTS)),
+        _ => None,
+    }
+}
+
+/// The framebuffer inside a bordered block
+#[derive(Copy, Clone)]
+struct Screen<'a> {
+    gpu: &'a Gpu,
+    /// Shown in place of the key hints while set
+    status: Option<&'a str>,
+    title: Option<&'a str>,
+    key_hints: Option<&'a str>,
+}
+
+impl Screen<'_> {
+    fn content(&self) -> impl Widget + '_ {
+        let gpu = self.gpu;
+        let (width, height) = (gpu.width(), gpu.height());
+        // XO-CHIP colours by plane: 1 = screen only, 2 = second plane only, 3 = both
+        let palette = [Color::Reset, Color::Cyan, Color::Magenta, Color::White];
+
+        // the bounds follow the active resolution so the canvas scales hi-res frames to fit
+        let canvas = Canvas::default()
+            .marker(Marker::Block)
+            .block(Block::bordered().title("Canvas"))
+            .x_bounds([0.0, width as f64])
+            .y_bounds([0.0, height as f64])
+            .paint(move |ctx| {
+                for y in 0..height {
+                    for x in 0..width {
+                        let color = gpu.color(y * width + x);
+                        if color != 0 {
+                            ctx.draw(&Rectangle {
+                                x: x as f64,
+                                // canvas y grows upwards, chip8 rows grow downwards
+                                y: (height - 1 - y) as f64,
+                                width: 1.0,
+                                height: 1.0,
+                                color: palette[color as usize],
+                            })
+                        }
+                    }
+                }
+            });
+        canvas
+    }
+}
+
+impl Widget for Screen<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = match self.title {
+            Some(title) => Title::from(format!(" {title} ").bold()),
+            None => Title::from(TextLine::from(vec![
+                " Canvas ".bold(),
+                "<3".red().bold(),
+                " Galus ".bold(),
+            ])),
+        };
+
+        let instructions = match self.status {
+            Some(status) => Title::from(format!(" {status} ").yellow().bold()),
+            None => Title::from(TextLine::from(vec![
+                " Keys ".into(),
+                "<1-4 Q-R A-F Z-V> ".blue().bold(),
+                " Save ".into(),
+                "<F1-F4> ".blue().bold(),
+                " Load ".into(),
+                "<F5-F8> ".blue().bold(),
+                " Rewind ".into(),
+                "<Backspace> ".blue().bold(),
+                " Debug ".into(),
+                "<Tab> ".blue().bold(),
+                " Quit ".into(),
+                "<Esc> ".blue().bold(),
+            ])),
+        };
+
+        let mut block = Block::bordered().title(title.alignment(Alignment::Right));
+        if let Some(hints) = self.key_hints {
+            block = block.title(
+                TextLine::from(vec![
+                    " Rom keys ".into(),
+                    format!("<{hints}> ").blue().bold(),
+                ])
+                .alignment(Alignment::Left),
+            );
+        }
+        let block = block
+            .title(
+                instructions
+                    .alignment(Alignment::Center)
+                    .position(Position::Bottom),
+            )
+            .border_set(border::THICK);
+
+        let inner = block.inner(area);
+        block.render(area, buf);
+        self.content().render(inner, buf);
+    }
+}
+
+/// Registers, disassembly around the pc and a hex view of memory
+#[derive(Copy, Clone)]
+struct DebugPanel<'a> {
+    emu: &'a Emulator,
+    /// First row of the memory view, None centres it on the pc
+    memory_scroll: Option<usize>,
+}
+
+impl DebugPanel<'_> {
+    fn registers(&self) -> Paragraph<'_> {
+        let cpu = &self.emu.cpu;
+        let mut lines: Vec<TextLine> = cpu
+            .registers
+            .chunks(4)
+            .enumerate()
+            .map(|(row, values)| {
+                let cells: Vec<String> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| format!("V{:X} {value:02X}", row * 4 + i))
+                    .collect();
+                TextLine::from(cells.join("  "))
+            })
+            .collect();
+        lines.push(TextLine::from(format!(
+            "I {:04X}  PC {:04X}  DT {:02X}  ST {:02X}",
+            cpu.index_register,
+            cpu.program_counter,
+            cpu.memory.delay_timer.get(),
+            cpu.memory.sound_timer.get(),
+        )));
+        let stack: Vec<String> = cpu.stack().iter().map(|a| format!("{a:04X}")).collect();
+        lines.push(TextLine::from(format!("Stack {}", stack.join(" "))));
+        let debugger = &self.emu.debugger;
+        let state = if let Some(reason) = debugger.stop_reason() {
+            format!(" PAUSED: {reason} ").yellow().bold()
+        } else if debugger.paused {
+            " PAUSED ".yellow().bold()
+        } else {
+            " RUNNING ".green().bold()
+        };
+        Paragraph::new(lines).block(
+            Block::bordered()
+                .title(" Registers ")
+                .title(Title::from(state).alignment(Alignment::Right)),
+        )
+    }
+
+    fn disassembly(&self, rows: usize) -> Paragraph<'_> {
+        let ram = &self.emu.cpu.memory.ram;
+        let pc = self.emu.cpu.program_counter;
+        let mut address = pc.saturating_sub(DISASSEMBLY_BEFORE * 2);
+        let mut lines = Vec::with_capacity(rows);
+        while lines.len() < rows && (address as usize) < ram.len() {
+            let (text, size) = instruction_at(ram, address);
+            let line = format!("{address:04X}  {text}");
+            lines.push(if address == pc {
+                TextLine::from(format!("> {line}").yellow().bold())
+            } else {
+                TextLine::from(format!("  {line}"))
+            });
+            address = address.wrapping_add(size);
+            if address == 0 {
+                break;
+            }
+        }
+        Paragraph::new(lines).block(Block::bordered().title(" Disassembly "))
+    }
+
+    fn memory(&self, rows: usize) -> Paragraph<'_> {
+        let cpu = &self.emu.cpu;
+        let ram = &cpu.memory.ram;
+        let (pc, index) = (cpu.program_counter as usize, cpu.index_register as usize);
+        let last_row = (ram.len() / MEMORY_ROW).saturating_sub(rows);
+        let first_row = self
+            .memory_scroll
+            .unwrap_or((pc / MEMORY_ROW).saturating_sub(rows / 2))
+            .min(last_row);
+        let lines: Vec<TextLine> = (first_row..first_row + rows)
+            .filter(|row| row * MEMORY_ROW < ram.len())
+            .map(|row| {
+                let start = row * MEMORY_ROW;
+                let mut spans = vec![Span::raw(format!("{start:04X}:"))];
+                for (address, byte) in ram[start..start + MEMORY_ROW].iter().enumerate() {
+                    let address = start + address;
+                    let style = if address == pc || address == pc + 1 {
+                        Style::new().black().on_yellow()
+                    } else if address == index {
+                        Style::new().black().on_cyan()
+                    } else {
+                        Style::new()
+                    };
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(format!("{byte:02X}"), style));
+                }
+                TextLine::from(spans)
+            })
+            .collect();
+        Paragraph::new(lines).block(
+            Block::bordered().title(" Memory ").title(
+                Title::from(TextLine::from(vec![
+                    " PC ".black().on_yellow(),
+                    " ".into(),
+                    " I ".black().on_cyan(),
+                    " ".into(),
+                ]))
+                .alignment(Alignment::Right),
+            ),
+        )
+    }
+}
+
+impl Widget for DebugPanel<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [registers, disassembly, memory, keys] = Layout::vertical([
+            Constraint::Length(8),
+            Constraint::Length(12),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        self.registers().render(registers, buf);
+        self.disassembly(disassembly.height.saturating_sub(2) as usize)
+            .render(disassembly, buf);
+        self.memory(memory.height.saturating_sub(2) as usize)
+            .render(memory, buf);
+        TextLine::from(vec![
+            " Pause ".into(),
+            "<p>".blue().bold(),
+            " Go ".into(),
+            "<g>".blue().bold(),
+            " Step ".into(),
+            "<i>".blue().bold(),
+            " Over ".into(),
+            "<o>".blue().bold(),
+            " Scroll ".into(),
+            "<PgUp PgDn Home>".blue().bold(),
+        ])
+        .render(keys, buf);
+    }
+}
+
+#[cfg(test)]
+mod tuitests {
+    use super::{chip8_key, slot_command, DebugPanel};
+    use crate::emu::{frontend::Command, Emulator};
+    use ratatui::{buffer::Buffer, crossterm::event::KeyCode, layout::Rect, widgets::Widget};
+
+    #[test]
+    fn test_keymap() {
+        // make sure our [1-4,q-r,a-f,z-v] maps to [0 - 16]
+        assert_eq!(chip8_key(KeyCode::Char('1')), Some(0));
+        assert_eq!(chip8_key(KeyCode::Char('r')), Some(7));
+        assert_eq!(chip8_key(KeyCode::Char('x')), Some(13));
+        assert_eq!(chip8_key(KeyCode::Char('v')), Some(15));
+        assert_eq!(chip8_key(KeyCode::Char('0')), None);
+        assert_eq!(chip8_key(KeyCode::Left), None);
+    }
+
+    #[test]
+    fn test_slot_keys() {
+        assert_eq!(slot_command(1), Some(Command::SaveSlot(1)));
+        assert_eq!(slot_command(4), Some(Command::SaveSlot(4)));
+        assert_eq!(slot_command(5), Some(Command::LoadSlot(1)));
+        assert_eq!(slot_command(8), Some(Command::LoadSlot(4)));
+        assert_eq!(slot_command(9), None);
+    }
+
+    #[test]
+    fn test_debug_panel() {
+        let mut emu = Emulator::new();
+        emu.cpu.memory.ram[0x200..0x204].copy_from_slice(&[0x00, 0xE0, 0x12, 0x00]);
+        emu.cpu.registers[0xA] = 0x42;
+        emu.cpu.index_register = 0x203;
+        emu.cpu.push_stack(0x2F0).unwrap();
+        emu.debugger.pause();
+
+        let area = Rect::new(0, 0, 60, 40);
+        let mut buf = Buffer::empty(area);
+        let panel = DebugPanel {
+            emu: &emu,
+            memory_scroll: None,
+        };
+        panel.render(area, &mut buf);
+        let text: Vec<String> = (0..area.height)
+            .map(|y| (0..area.width).map(|x| buf[(x, y)].symbol()).collect())
+            .collect();
+        let text = text.join("\n");
+        assert!(text.contains("VA 42"));
+        assert!(text.contains("I 0203  PC 0200"));
+        assert!(text.contains("Stack 02F0"));
+        assert!(text.contains("PAUSED"));
+        assert!(text.contains("> 0200  CLS"));
+        assert!(text.contains("  0202  JP 0x200"));
+        assert!(text.contains("0200: 00 E0 12 00"));
+    }
+}
+
+//use ratatui::{
+//    backend::CrosstermBackend,
+//    crossterm::{
+//        execute,
+//        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+//    },
+//    Terminal,
+//};
+
+//
+//use ratatui::{
+//    style::Color,
+//    widgets::{canvas::*, *},
+//};
+//
+//Canvas::default()
+//    .block(Block::bordered().title("Canvas"))
+//    .x_bounds([-180.0,180.0])
+//    .y_bounds([-90.0,90.0])
+//    .paint(|ctx| {
+//        ctx.draw(&Map {
+//            resolution: MapResolution::High,
+//            color: Color::White,
+//        });
+//        ctx.layer();
+//        ctx.draw(&Line {
+//            x1: 0.0,
+//            y1: 10.0,
+//            x2: 10.0,
+//            y2: 10.0,
+//            color: Color::White,
+//        });
+//        ctx.draw(&Rectangle {
+//            x: 10.0,
+//            y: 20.0,
+//            width: 10.0,
+//            height: 10.0,
+//            color: Color::Red,
+//        });
+//    });
@@ -1,282 +1,116 @@
 // Contains the graphics processing.
-use color_eyre::{
-    eyre::{bail, WrapErr},
-    Report, Result,
-};
-
-use ratatui::{
-    backend::CrosstermBackend,
-    buffer::Buffer,
-    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
-    crossterm::{
-        execute,
-        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    },
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Stylize},
-    symbols::{border, Marker},
-    text::Line as TextLine,
-    text::Text,
-    widgets::{
-        block::{Position, Title},
-        canvas::{Canvas, Rectangle},
-        Block, Paragraph, Widget,
-    },
-    Frame, Terminal,
-};
-
-use std::io::{self, stdout, Stdout};
-/// A type alias for the terminal type used in this application
-pub type Tui = Terminal<CrosstermBackend<Stdout>>;
+// The Gpu is only the framebuffer, a Frontend decides how it ends up on screen.
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+/// SUPER-CHIP high resolution mode, switched on by 00FF
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
 #[derive(Debug)]
 pub struct Gpu {
-    pub counter: u8,
-    pub exit: bool,
-    pub screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    pub hires: bool,
+    /// Row-major pixels of the active resolution, `width() * height()` long
+    pub screen: Vec<bool>,
+    /// XO-CHIP's second bitplane, laid out like `screen`
+    pub second_plane: Vec<bool>,
+    /// Bitplanes that drawing, clearing and scrolling act on, set by Fn01.
+    /// Bit 0 is `screen` and bit 1 is `second_plane`.
+    pub selected_planes: u8,
 }
 
-//impl Default for Gpu {
-//    fn default() -> Self {
-//        let screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-//        Self {
-//            counter: 0,
-//            exit: false,
-//            screen,
-//        }
-//    }
-//}
+impl Default for Gpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Gpu {
     pub fn new() -> Self {
         Self {
-            counter: 0,
-            exit: false,
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            hires: false,
+            screen: vec![false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            second_plane: vec![false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            selected_planes: 1,
         }
     }
 
-    pub fn run(&mut self, terminal: &mut Tui) -> Result<()> {
-        while !self.exit {
-            //
-
-            // Render
-            terminal.draw(|frame| self.render_frame(frame))?;
-
-            // Handle Input
-            self.handle_events().wrap_err("handle events failed")?;
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            SCREEN_WIDTH
         }
-        Ok(())
-    }
-
-    fn render_frame(&self, frame: &mut Frame) {
-        frame.render_widget(self, frame.area());
     }
 
-    pub fn handle_events(&mut self) -> Result<u8> {
-        //color_eyre::install()?; // error hooks
-        match event::read()? {
-            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => self
-                .handle_key_event(key_event)
-                .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}")),
-            _ => Ok(255),
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            SCREEN_HEIGHT
         }
     }
 
-    pub fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<u8> {
-        match key_event.code {
-            KeyCode::Char('0') => {
-                self.exit();
-                return Ok(255);
-            }
-            KeyCode::Left => {
-                self.decrement_counter()?;
-                return Ok(254);
-            }
-            KeyCode::Right => {
-                self.increment_counter()?;
-                return Ok(253);
-            }
-
-            // Chip8 valid 16 chars
-            KeyCode::Char('1') => Ok::<u8, Report>(0),
-            KeyCode::Char('2') => Ok(1),
-            KeyCode::Char('3') => Ok(2),
-            KeyCode::Char('4') => Ok(3),
-            KeyCode::Char('q') => Ok(4),
-            KeyCode::Char('w') => Ok(5),
-            KeyCode::Char('e') => Ok(6),
-            KeyCode::Char('r') => Ok(7),
-            KeyCode::Char('a') => Ok(8),
-            KeyCode::Char('s') => Ok(9),
-            KeyCode::Char('d') => Ok(10),
-            KeyCode::Char('f') => Ok(11),
-            KeyCode::Char('z') => Ok(12),
-            KeyCode::Char('x') => Ok(13),
-            KeyCode::Char('c') => Ok(14),
-            KeyCode::Char('v') => Ok(15),
-            _ => Ok(222),
-        }
-        //Ok(111)
+    /// The selected bitplanes, `screen` first
+    pub fn planes_mut(&mut self) -> impl Iterator<Item = &mut Vec<bool>> + '_ {
+        let selected = self.selected_planes;
+        [&mut self.screen, &mut self.second_plane]
+            .into_iter()
+            .enumerate()
+            .filter(move |(bit, _)| selected & (1 << bit) != 0)
+            .map(|(_, plane)| plane)
     }
 
-    fn exit(&mut self) {
-        self.exit = true;
+    /// Colour of the pixel at `index`, 0-3, combining one bit from each plane
+    pub fn color(&self, index: usize) -> u8 {
+        self.screen[index] as u8 | (self.second_plane[index] as u8) << 1
     }
 
-    // galus: There is an overflow bug here left for educational porpoises 🎓 🐬
-    fn increment_counter(&mut self) -> Result<()> {
-        self.counter += 1;
-        if self.counter > 2 {
-            bail!("counter overflow");
+    /// Clear the selected planes
+    pub fn clear(&mut self) {
+        for plane in self.planes_mut() {
+            plane.fill(false);
         }
-        Ok(())
-    }
-
-    fn decrement_counter(&mut self) -> Result<()> {
-        self.counter -= 1;
-        Ok(())
-    }
-
-    fn content(&self) -> impl Widget + '_ {
-        let mut screen = self.screen.clone();
-        screen[1000..1099].copy_from_slice(&[true; 99]);
-
-        let canvas = Canvas::default()
-            .marker(Marker::Block)
-            .block(Block::bordered().title("Canvas"))
-            .x_bounds([0.0, SCREEN_WIDTH as f64])
-            .y_bounds([0.0, SCREEN_HEIGHT as f64])
-            .paint(move |ctx| {
-                for y in 0..SCREEN_HEIGHT {
-                    for x in 0..SCREEN_WIDTH {
-                        let index = y * SCREEN_WIDTH + x;
-                        if screen[index] {
-                            ctx.draw(&Rectangle {
-                                x: x as f64,
-                                y: y as f64,
-                                width: 1.0,
-                                height: 1.0,
-                                color: Color::Cyan,
-                            })
-                        }
-                    }
-                }
-            });
-        canvas
     }
 
-    /// Initialize the terminal
-    pub fn init(&self) -> io::Result<Tui> {
-        execute!(stdout(), EnterAlternateScreen)?;
-        // What is raw_mode?
-        //   Starts taking input immediately w/o waiting for newline
-        //   and prevents typed keys being echo'd back
-        enable_raw_mode()?;
-        Self::set_panic_hook();
-        Terminal::new(CrosstermBackend::new(stdout()))
+    /// Switch between 64x32 and 128x64, 00FF/00FE. Both planes are cleared either way.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = vec![false; self.width() * self.height()];
+        self.second_plane = vec![false; self.width() * self.height()];
     }
 
-    /// Restore the terminal to its original state
-    pub fn restore(&self) -> io::Result<()> {
-        execute!(stdout(), LeaveAlternateScreen)?;
-        disable_raw_mode()?;
-        Ok(())
+    /// 00Cn, move every row down by `rows` pixels, blank rows come in at the top
+    pub fn scroll_down(&mut self, rows: usize) {
+        let width = self.width();
+        for plane in self.planes_mut() {
+            let shift = (rows * width).min(plane.len());
+            let len = plane.len();
+            plane.copy_within(0..len - shift, shift);
+            plane[..shift].fill(false);
+        }
     }
 
-    fn set_panic_hook() {
-        let hook = std::panic::take_hook();
-        std::panic::set_hook(Box::new(move |panic_info| {
-            let _ = Self::restore(&Self::new());
-            hook(panic_info);
-        }))
+    /// 00FB, move every column right by `columns` pixels
+    pub fn scroll_right(&mut self, columns: usize) {
+        let width = self.width();
+        let columns = columns.min(width);
+        for plane in self.planes_mut() {
+            for row in plane.chunks_mut(width) {
+                row.copy_within(0..width - columns, columns);
+                row[..columns].fill(false);
+            }
+        }
     }
-}
-
-impl Widget for &Gpu {
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        let title = Title::from(TextLine::from(vec![
-            " Canvas ".bold(),
-            "<3".red().bold(),
-            " Galus ".bold(),
-        ]));
-
-        let instructions = Title::from(TextLine::from(vec![
-            " Left ".into(),
-            "<H> ".blue().bold(),
-            " Right ".into(),
-            "<L> ".blue().bold(),
-            " Quit ".into(),
-            "<Q> ".blue().bold(),
-        ]));
 
-        let block = Block::bordered()
-            .title(title.alignment(Alignment::Right))
-            .title(
-                instructions
-                    .alignment(Alignment::Center)
-                    .position(Position::Bottom),
-            )
-            .border_set(border::THICK);
-
-        let counter_text = Text::from(vec![TextLine::from(vec![
-            " Value: ".into(),
-            self.counter.to_string().yellow(),
-            " ".into(),
-        ])]);
-
-        let paragraph = Paragraph::new(counter_text).alignment(Alignment::Center);
-
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Length(1), Constraint::Min(0)])
-            .split(block.inner(area));
-
-        block.render(area, buf);
-        paragraph.render(chunks[0], buf);
-        self.content().render(chunks[1], buf);
+    /// 00FC, move every column left by `columns` pixels
+    pub fn scroll_left(&mut self, columns: usize) {
+        let width = self.width();
+        let columns = columns.min(width);
+        for plane in self.planes_mut() {
+            for row in plane.chunks_mut(width) {
+                row.copy_within(columns.., 0);
+                row[width - columns..].fill(false);
+            }
+        }
     }
 }
-
-//use ratatui::{
-//    backend::CrosstermBackend,
-//    crossterm::{
-//        execute,
-//        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-//    },
-//    Terminal,
-//};
-
-//
-//use ratatui::{
-//    style::Color,
-//    widgets::{canvas::*, *},
-//};
-//
-//Canvas::default()
-//    .block(Block::bordered().title("Canvas"))
-//    .x_bounds([-180.0,180.0])
-//    .y_bounds([-90.0,90.0])
-//    .paint(|ctx| {
-//        ctx.draw(&Map {
-//            resolution: MapResolution::High,
-//            color: Color::White,
-//        });
-//        ctx.layer();
-//        ctx.draw(&Line {
-//            x1: 0.0,
-//            y1: 10.0,
-//            x2: 10.0,
-//            y2: 10.0,
-//            color: Color::White,
-//        });
-//        ctx.draw(&Rectangle {
-//            x: 10.0,
-//            y: 20.0,
-//            width: 10.0,
-//            height: 10.0,
-//            color: Color::Red,
-//        });
-//    });
@@ -1,7 +1,76 @@
-#[derive(Debug)]
-pub struct Keypad {}
+/// Number of keys on the COSMAC VIP hex keypad, 0-F
+pub const KEY_COUNT: usize = 16;
+
+/// Which of the 16 hex keys are held down. The frontend updates it between frames and the
+/// key instructions only ever look at it, so they never block.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Keypad {
+    pressed: [bool; KEY_COUNT],
+}
 impl Keypad {
     pub(crate) fn new() -> Self {
-        Self {}
+        Self {
+            pressed: [false; KEY_COUNT],
+        }
+    }
+
+    /// Keys past F are ignored, only the low nibble of a register names a key
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.pressed[(key & 0xF) as usize]
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.pressed[(key & 0xF) as usize] = true;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.pressed[(key & 0xF) as usize] = false;
+    }
+
+    pub fn release_all(&mut self) {
+        self.pressed = [false; KEY_COUNT];
+    }
+
+    /// Bit per key, key 0 in the lowest bit
+    pub fn bits(&self) -> u16 {
+        (0..KEY_COUNT).fold(0, |bits, key| bits | (self.pressed[key] as u16) << key)
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        let mut pad = Self::new();
+        for key in 0..KEY_COUNT {
+            pad.pressed[key] = bits & (1 << key) != 0;
+        }
+        pad
+    }
+}
+
+/// Fx0A's halt: the cpu waits until some key goes down and comes back up,
+/// then stores that key in `register`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyWait {
+    pub register: u8,
+    /// Bit per key that has been seen held since the wait started
+    pub(crate) seen_pressed: u16,
+}
+impl KeyWait {
+    pub fn new(register: u8) -> Self {
+        Self {
+            register,
+            seen_pressed: 0,
+        }
+    }
+
+    /// Look at the keypad again, returning the first key that has been pressed and released
+    pub fn update(&mut self, pad: &Keypad) -> Option<u8> {
+        for key in 0..KEY_COUNT as u8 {
+            let bit = 1 << key;
+            if pad.is_pressed(key) {
+                self.seen_pressed |= bit;
+            } else if self.seen_pressed & bit != 0 {
+                return Some(key);
+            }
+        }
+        None
     }
 }
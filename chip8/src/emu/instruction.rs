@@ -0,0 +1,493 @@
+// Decodes raw OpCodes into typed Instructions with their operands pulled out.
+use std::fmt;
+
+use super::iset::{Nibbles, OpCode};
+
+/// A decoded chip8 instruction, including the SUPER-CHIP 1.1 and XO-CHIP extensions.
+/// `x` and `y` are register indices, `nn` an 8-bit immediate, `nnn` a 12-bit address
+/// and `n` the 4-bit sprite height.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0nnn - SYS addr
+    Sys { nnn: u16 },
+    /// 00Cn - SCD nibble (SUPER-CHIP)
+    ScrollDown { n: u8 },
+    /// 00E0 - CLS
+    Cls,
+    /// 00EE - RET
+    Ret,
+    /// 00FB - SCR (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC - SCL (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD - EXIT (SUPER-CHIP)
+    Exit,
+    /// 00FE - LOW (SUPER-CHIP)
+    LowRes,
+    /// 00FF - HIGH (SUPER-CHIP)
+    HighRes,
+    /// 1nnn - JP addr
+    Jump { nnn: u16 },
+    /// 2nnn - CALL addr
+    Call { nnn: u16 },
+    /// 3xnn - SE Vx, byte
+    SkipEqImm { x: u8, nn: u8 },
+    /// 4xnn - SNE Vx, byte
+    SkipNeImm { x: u8, nn: u8 },
+    /// 5xy0 - SE Vx, Vy
+    SkipEqReg { x: u8, y: u8 },
+    /// 5xy2 - LD [I], Vx-Vy (XO-CHIP)
+    StoreRange { x: u8, y: u8 },
+    /// 5xy3 - LD Vx-Vy, [I] (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    /// 6xnn - LD Vx, byte
+    LoadImm { x: u8, nn: u8 },
+    /// 7xnn - ADD Vx, byte
+    AddImm { x: u8, nn: u8 },
+    /// 8xy0 - LD Vx, Vy
+    LoadReg { x: u8, y: u8 },
+    /// 8xy1 - OR Vx, Vy
+    Or { x: u8, y: u8 },
+    /// 8xy2 - AND Vx, Vy
+    And { x: u8, y: u8 },
+    /// 8xy3 - XOR Vx, Vy
+    Xor { x: u8, y: u8 },
+    /// 8xy4 - ADD Vx, Vy
+    AddReg { x: u8, y: u8 },
+    /// 8xy5 - SUB Vx, Vy
+    SubReg { x: u8, y: u8 },
+    /// 8xy6 - SHR Vx {, Vy}
+    ShiftRight { x: u8, y: u8 },
+    /// 8xy7 - SUBN Vx, Vy
+    SubN { x: u8, y: u8 },
+    /// 8xyE - SHL Vx {, Vy}
+    ShiftLeft { x: u8, y: u8 },
+    /// 9xy0 - SNE Vx, Vy
+    SkipNeReg { x: u8, y: u8 },
+    /// Annn - LD I, addr
+    LoadIndex { nnn: u16 },
+    /// Bnnn - JP V0, addr
+    JumpOffset { nnn: u16 },
+    /// Cxnn - RND Vx, byte
+    Random { x: u8, nn: u8 },
+    /// Dxyn - DRW Vx, Vy, nibble. Dxy0 draws a 16x16 sprite (SUPER-CHIP)
+    Draw { x: u8, y: u8, n: u8 },
+    /// F000 nnnn - LD I, LONG addr (XO-CHIP).
+    /// The 16-bit address is the word after the opcode, so this is the only 4 byte instruction.
+    LongIndex,
+    /// Fn01 - PLANE n (XO-CHIP), `n` is a bitmask of the planes to draw on
+    Plane { n: u8 },
+    /// Ex9E - SKP Vx
+    SkipKey { x: u8 },
+    /// ExA1 - SKNP Vx
+    SkipNotKey { x: u8 },
+    /// Fx07 - LD Vx, DT
+    LoadDelay { x: u8 },
+    /// Fx0A - LD Vx, K
+    WaitKey { x: u8 },
+    /// Fx15 - LD DT, Vx
+    SetDelay { x: u8 },
+    /// Fx18 - LD ST, Vx
+    SetSound { x: u8 },
+    /// Fx1E - ADD I, Vx
+    AddIndex { x: u8 },
+    /// Fx29 - LD F, Vx
+    LoadFont { x: u8 },
+    /// Fx30 - LD HF, Vx (SUPER-CHIP)
+    LoadBigFont { x: u8 },
+    /// Fx33 - LD B, Vx
+    StoreBcd { x: u8 },
+    /// Fx55 - LD [I], Vx
+    StoreRegs { x: u8 },
+    /// Fx65 - LD Vx, [I]
+    LoadRegs { x: u8 },
+    /// Fx75 - LD R, Vx (SUPER-CHIP)
+    StoreFlags { x: u8 },
+    /// Fx85 - LD Vx, R (SUPER-CHIP)
+    LoadFlags { x: u8 },
+}
+
+impl Instruction {
+    /// Bytes the instruction takes up in memory, including the operand word of F000.
+    pub fn size(self) -> u16 {
+        match self {
+            Instruction::LongIndex => 4,
+            _ => 2,
+        }
+    }
+
+    /// Pack the instruction back into its OpCode, the inverse of `decode`.
+    pub fn encode(self) -> OpCode {
+        use Instruction::*;
+        let xnn = |op: u16, x: u8, nn: u8| op << 12 | (x as u16) << 8 | nn as u16;
+        let xyn = |op: u16, x: u8, y: u8, n: u8| xnn(op, x, y << 4 | n);
+        let raw = match self {
+            Sys { nnn } => nnn,
+            ScrollDown { n } => 0x00C0 | n as u16,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowRes => 0x00FE,
+            HighRes => 0x00FF,
+            Jump { nnn } => 0x1000 | nnn,
+            Call { nnn } => 0x2000 | nnn,
+            SkipEqImm { x, nn } => xnn(3, x, nn),
+            SkipNeImm { x, nn } => xnn(4, x, nn),
+            SkipEqReg { x, y } => xyn(5, x, y, 0),
+            StoreRange { x, y } => xyn(5, x, y, 2),
+            LoadRange { x, y } => xyn(5, x, y, 3),
+            LoadImm { x, nn } => xnn(6, x, nn),
+            AddImm { x, nn } => xnn(7, x, nn),
+            LoadReg { x, y } => xyn(8, x, y, 0),
+            Or { x, y } => xyn(8, x, y, 1),
+            And { x, y } => xyn(8, x, y, 2),
+            Xor { x, y } => xyn(8, x, y, 3),
+            AddReg { x, y } => xyn(8, x, y, 4),
+            SubReg { x, y } => xyn(8, x, y, 5),
+            ShiftRight { x, y } => xyn(8, x, y, 6),
+            SubN { x, y } => xyn(8, x, y, 7),
+            ShiftLeft { x, y } => xyn(8, x, y, 0xE),
+            SkipNeReg { x, y } => xyn(9, x, y, 0),
+            LoadIndex { nnn } => 0xA000 | nnn,
+            JumpOffset { nnn } => 0xB000 | nnn,
+            Random { x, nn } => xnn(0xC, x, nn),
+            Draw { x, y, n } => xyn(0xD, x, y, n),
+            LongIndex => 0xF000,
+            Plane { n } => xnn(0xF, n, 0x01),
+            SkipKey { x } => xnn(0xE, x, 0x9E),
+            SkipNotKey { x } => xnn(0xE, x, 0xA1),
+            LoadDelay { x } => xnn(0xF, x, 0x07),
+            WaitKey { x } => xnn(0xF, x, 0x0A),
+            SetDelay { x } => xnn(0xF, x, 0x15),
+            SetSound { x } => xnn(0xF, x, 0x18),
+            AddIndex { x } => xnn(0xF, x, 0x1E),
+            LoadFont { x } => xnn(0xF, x, 0x29),
+            LoadBigFont { x } => xnn(0xF, x, 0x30),
+            StoreBcd { x } => xnn(0xF, x, 0x33),
+            StoreRegs { x } => xnn(0xF, x, 0x55),
+            LoadRegs { x } => xnn(0xF, x, 0x65),
+            StoreFlags { x } => xnn(0xF, x, 0x75),
+            LoadFlags { x } => xnn(0xF, x, 0x85),
+        };
+        OpCode(raw)
+    }
+
+    /// The opcode with its operands as letters, e.g. `8xy4`. Dxy0 is told apart from Dxyn
+    /// since it draws SUPER-CHIP's 16x16 sprites.
+    pub fn pattern(self) -> &'static str {
+        use Instruction::*;
+        match self {
+            Sys { .. } => "0nnn",
+            ScrollDown { .. } => "00Cn",
+            Cls => "00E0",
+            Ret => "00EE",
+            ScrollRight => "00FB",
+            ScrollLeft => "00FC",
+            Exit => "00FD",
+            LowRes => "00FE",
+            HighRes => "00FF",
+            Jump { .. } => "1nnn",
+            Call { .. } => "2nnn",
+            SkipEqImm { .. } => "3xnn",
+            SkipNeImm { .. } => "4xnn",
+            SkipEqReg { .. } => "5xy0",
+            StoreRange { .. } => "5xy2",
+            LoadRange { .. } => "5xy3",
+            LoadImm { .. } => "6xnn",
+            AddImm { .. } => "7xnn",
+            LoadReg { .. } => "8xy0",
+            Or { .. } => "8xy1",
+            And { .. } => "8xy2",
+            Xor { .. } => "8xy3",
+            AddReg { .. } => "8xy4",
+            SubReg { .. } => "8xy5",
+            ShiftRight { .. } => "8xy6",
+            SubN { .. } => "8xy7",
+            ShiftLeft { .. } => "8xyE",
+            SkipNeReg { .. } => "9xy0",
+            LoadIndex { .. } => "Annn",
+            JumpOffset { .. } => "Bnnn",
+            Random { .. } => "Cxnn",
+            Draw { n: 0, .. } => "Dxy0",
+            Draw { .. } => "Dxyn",
+            LongIndex => "F000",
+            Plane { .. } => "Fn01",
+            SkipKey { .. } => "Ex9E",
+            SkipNotKey { .. } => "ExA1",
+            LoadDelay { .. } => "Fx07",
+            WaitKey { .. } => "Fx0A",
+            SetDelay { .. } => "Fx15",
+            SetSound { .. } => "Fx18",
+            AddIndex { .. } => "Fx1E",
+            LoadFont { .. } => "Fx29",
+            LoadBigFont { .. } => "Fx30",
+            StoreBcd { .. } => "Fx33",
+            StoreRegs { .. } => "Fx55",
+            LoadRegs { .. } => "Fx65",
+            StoreFlags { .. } => "Fx75",
+            LoadFlags { .. } => "Fx85",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction with the usual Cowgod mnemonics, e.g. `LD Vx, byte`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            Sys { nnn } => write!(f, "SYS 0x{nnn:03X}"),
+            ScrollDown { n } => write!(f, "SCD {n}"),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LowRes => write!(f, "LOW"),
+            HighRes => write!(f, "HIGH"),
+            Jump { nnn } => write!(f, "JP 0x{nnn:03X}"),
+            Call { nnn } => write!(f, "CALL 0x{nnn:03X}"),
+            SkipEqImm { x, nn } => write!(f, "SE V{x:X}, 0x{nn:02X}"),
+            SkipNeImm { x, nn } => write!(f, "SNE V{x:X}, 0x{nn:02X}"),
+            SkipEqReg { x, y } => write!(f, "SE V{x:X}, V{y:X}"),
+            StoreRange { x, y } => write!(f, "LD [I], V{x:X}-V{y:X}"),
+            LoadRange { x, y } => write!(f, "LD V{x:X}-V{y:X}, [I]"),
+            LoadImm { x, nn } => write!(f, "LD V{x:X}, 0x{nn:02X}"),
+            AddImm { x, nn } => write!(f, "ADD V{x:X}, 0x{nn:02X}"),
+            LoadReg { x, y } => write!(f, "LD V{x:X}, V{y:X}"),
+            Or { x, y } => write!(f, "OR V{x:X}, V{y:X}"),
+            And { x, y } => write!(f, "AND V{x:X}, V{y:X}"),
+            Xor { x, y } => write!(f, "XOR V{x:X}, V{y:X}"),
+            AddReg { x, y } => write!(f, "ADD V{x:X}, V{y:X}"),
+            SubReg { x, y } => write!(f, "SUB V{x:X}, V{y:X}"),
+            ShiftRight { x, y } => write!(f, "SHR V{x:X}, V{y:X}"),
+            SubN { x, y } => write!(f, "SUBN V{x:X}, V{y:X}"),
+            ShiftLeft { x, y } => write!(f, "SHL V{x:X}, V{y:X}"),
+            SkipNeReg { x, y } => write!(f, "SNE V{x:X}, V{y:X}"),
+            LoadIndex { nnn } => write!(f, "LD I, 0x{nnn:03X}"),
+            JumpOffset { nnn } => write!(f, "JP V0, 0x{nnn:03X}"),
+            Random { x, nn } => write!(f, "RND V{x:X}, 0x{nn:02X}"),
+            Draw { x, y, n } => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            LongIndex => write!(f, "LD I, LONG"),
+            Plane { n } => write!(f, "PLANE {n}"),
+            SkipKey { x } => write!(f, "SKP V{x:X}"),
+            SkipNotKey { x } => write!(f, "SKNP V{x:X}"),
+            LoadDelay { x } => write!(f, "LD V{x:X}, DT"),
+            WaitKey { x } => write!(f, "LD V{x:X}, K"),
+            SetDelay { x } => write!(f, "LD DT, V{x:X}"),
+            SetSound { x } => write!(f, "LD ST, V{x:X}"),
+            AddIndex { x } => write!(f, "ADD I, V{x:X}"),
+            LoadFont { x } => write!(f, "LD F, V{x:X}"),
+            LoadBigFont { x } => write!(f, "LD HF, V{x:X}"),
+            StoreBcd { x } => write!(f, "LD B, V{x:X}"),
+            StoreRegs { x } => write!(f, "LD [I], V{x:X}"),
+            LoadRegs { x } => write!(f, "LD V{x:X}, [I]"),
+            StoreFlags { x } => write!(f, "LD R, V{x:X}"),
+            LoadFlags { x } => write!(f, "LD V{x:X}, R"),
+        }
+    }
+}
+
+/// The OpCode doesn't match any instruction we know how to execute.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: OpCode,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown opcode {:04X}", self.opcode.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Split an OpCode into the Instruction it encodes.
+pub fn decode(opcode: OpCode) -> Result<Instruction, DecodeError> {
+    use Instruction::*;
+    let (_, x, y, n) = opcode.into_tuple();
+    let nnn = opcode.0 & 0x0FFF;
+    let nn = (opcode.0 & 0x00FF) as u8;
+    let instruction = match opcode.into_tuple() {
+        (0, 0, 0xC, _) => ScrollDown { n },
+        (0, 0, 0xE, 0) => Cls,
+        (0, 0, 0xE, 0xE) => Ret,
+        (0, 0, 0xF, 0xB) => ScrollRight,
+        (0, 0, 0xF, 0xC) => ScrollLeft,
+        (0, 0, 0xF, 0xD) => Exit,
+        (0, 0, 0xF, 0xE) => LowRes,
+        (0, 0, 0xF, 0xF) => HighRes,
+        (0, _, _, _) => Sys { nnn },
+        (1, _, _, _) => Jump { nnn },
+        (2, _, _, _) => Call { nnn },
+        (3, _, _, _) => SkipEqImm { x, nn },
+        (4, _, _, _) => SkipNeImm { x, nn },
+        (5, _, _, 0) => SkipEqReg { x, y },
+        (5, _, _, 2) => StoreRange { x, y },
+        (5, _, _, 3) => LoadRange { x, y },
+        (6, _, _, _) => LoadImm { x, nn },
+        (7, _, _, _) => AddImm { x, nn },
+        (8, _, _, 0) => LoadReg { x, y },
+        (8, _, _, 1) => Or { x, y },
+        (8, _, _, 2) => And { x, y },
+        (8, _, _, 3) => Xor { x, y },
+        (8, _, _, 4) => AddReg { x, y },
+        (8, _, _, 5) => SubReg { x, y },
+        (8, _, _, 6) => ShiftRight { x, y },
+        (8, _, _, 7) => SubN { x, y },
+        (8, _, _, 0xE) => ShiftLeft { x, y },
+        (9, _, _, 0) => SkipNeReg { x, y },
+        (0xA, _, _, _) => LoadIndex { nnn },
+        (0xB, _, _, _) => JumpOffset { nnn },
+        (0xC, _, _, _) => Random { x, nn },
+        (0xD, _, _, _) => Draw { x, y, n },
+        (0xE, _, 9, 0xE) => SkipKey { x },
+        (0xE, _, 0xA, 1) => SkipNotKey { x },
+        (0xF, 0, 0, 0) => LongIndex,
+        (0xF, _, 0, 1) => Plane { n: x },
+        (0xF, _, 0, 7) => LoadDelay { x },
+        (0xF, _, 0, 0xA) => WaitKey { x },
+        (0xF, _, 1, 5) => SetDelay { x },
+        (0xF, _, 1, 8) => SetSound { x },
+        (0xF, _, 1, 0xE) => AddIndex { x },
+        (0xF, _, 2, 9) => LoadFont { x },
+        (0xF, _, 3, 0) => LoadBigFont { x },
+        (0xF, _, 3, 3) => StoreBcd { x },
+        (0xF, _, 5, 5) => StoreRegs { x },
+        (0xF, _, 6, 5) => LoadRegs { x },
+        (0xF, _, 7, 5) => StoreFlags { x },
+        (0xF, _, 8, 5) => LoadFlags { x },
+        _ => return Err(DecodeError { opcode }),
+    };
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod decodetests {
+    use super::{decode, DecodeError, Instruction};
+    use crate::emu::iset::OpCode;
+
+    #[test]
+    fn test_decode_operands() {
+        assert_eq!(
+            decode(OpCode(0x7A3F)),
+            Ok(Instruction::AddImm { x: 0xA, nn: 0x3F })
+        );
+        assert_eq!(decode(OpCode(0x2ABC)), Ok(Instruction::Call { nnn: 0xABC }));
+        assert_eq!(
+            decode(OpCode(0x8C5E)),
+            Ok(Instruction::ShiftLeft { x: 0xC, y: 5 })
+        );
+        assert_eq!(
+            decode(OpCode(0xD125)),
+            Ok(Instruction::Draw { x: 1, y: 2, n: 5 })
+        );
+        assert_eq!(decode(OpCode(0xF965)), Ok(Instruction::LoadRegs { x: 9 }));
+    }
+
+    #[test]
+    fn test_decode_zero_page() {
+        // 00E0 and 00EE must win over the 0nnn catch-all
+        assert_eq!(decode(OpCode(0x00E0)), Ok(Instruction::Cls));
+        assert_eq!(decode(OpCode(0x00EE)), Ok(Instruction::Ret));
+        assert_eq!(decode(OpCode(0x0123)), Ok(Instruction::Sys { nnn: 0x123 }));
+    }
+
+    #[test]
+    fn test_decode_superchip() {
+        assert_eq!(decode(OpCode(0x00C4)), Ok(Instruction::ScrollDown { n: 4 }));
+        assert_eq!(decode(OpCode(0x00FB)), Ok(Instruction::ScrollRight));
+        assert_eq!(decode(OpCode(0x00FC)), Ok(Instruction::ScrollLeft));
+        assert_eq!(decode(OpCode(0x00FD)), Ok(Instruction::Exit));
+        assert_eq!(decode(OpCode(0x00FE)), Ok(Instruction::LowRes));
+        assert_eq!(decode(OpCode(0x00FF)), Ok(Instruction::HighRes));
+        assert_eq!(
+            decode(OpCode(0xF230)),
+            Ok(Instruction::LoadBigFont { x: 2 })
+        );
+        assert_eq!(decode(OpCode(0xF775)), Ok(Instruction::StoreFlags { x: 7 }));
+        assert_eq!(decode(OpCode(0xF785)), Ok(Instruction::LoadFlags { x: 7 }));
+    }
+
+    #[test]
+    fn test_decode_xochip() {
+        assert_eq!(decode(OpCode(0xF000)), Ok(Instruction::LongIndex));
+        assert_eq!(decode(OpCode(0xF000)).unwrap().size(), 4);
+        assert_eq!(decode(OpCode(0xF201)), Ok(Instruction::Plane { n: 2 }));
+        assert_eq!(
+            decode(OpCode(0x5142)),
+            Ok(Instruction::StoreRange { x: 1, y: 4 })
+        );
+        assert_eq!(
+            decode(OpCode(0x5413)),
+            Ok(Instruction::LoadRange { x: 4, y: 1 })
+        );
+        assert_eq!(
+            decode(OpCode(0xF100)),
+            Err(DecodeError {
+                opcode: OpCode(0xF100)
+            })
+        );
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        for raw in 0..=0xFFFF {
+            if let Ok(instruction) = decode(OpCode(raw)) {
+                assert_eq!(instruction.encode(), OpCode(raw), "{instruction}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_mnemonics() {
+        let text = |raw| decode(OpCode(raw)).unwrap().to_string();
+        assert_eq!(text(0x6A3F), "LD VA, 0x3F");
+        assert_eq!(text(0xD125), "DRW V1, V2, 5");
+        assert_eq!(text(0xB2F0), "JP V0, 0x2F0");
+        assert_eq!(text(0xF355), "LD [I], V3");
+        assert_eq!(text(0xF30A), "LD V3, K");
+        assert_eq!(text(0x00CA), "SCD 10");
+        assert_eq!(text(0xD120), "DRW V1, V2, 0");
+        assert_eq!(text(0xF430), "LD HF, V4");
+        assert_eq!(text(0xF575), "LD R, V5");
+        assert_eq!(text(0xF585), "LD V5, R");
+        assert_eq!(text(0x5142), "LD [I], V1-V4");
+        assert_eq!(text(0x5413), "LD V4-V1, [I]");
+        assert_eq!(text(0xF301), "PLANE 3");
+    }
+
+    #[test]
+    fn test_patterns() {
+        let pattern = |raw| decode(OpCode(raw)).unwrap().pattern();
+        assert_eq!(pattern(0x8C5E), "8xyE");
+        assert_eq!(pattern(0xD125), "Dxyn");
+        assert_eq!(pattern(0xD120), "Dxy0");
+        assert_eq!(pattern(0xF301), "Fn01");
+        // every opcode fits its pattern once the letters are filled in
+        for raw in 0..=0xFFFF {
+            if let Ok(instruction) = decode(OpCode(raw)) {
+                let digits = format!("{raw:04X}");
+                let fits = instruction
+                    .pattern()
+                    .chars()
+                    .zip(digits.chars())
+                    .all(|(p, d)| p.is_ascii_lowercase() || p == d);
+                assert!(fits, "{digits} doesn't fit {}", instruction.pattern());
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        for raw in [0x5121, 0x800F, 0x9AB1, 0xE1FF, 0xF0FF] {
+            let opcode = OpCode(raw);
+            assert_eq!(decode(opcode), Err(DecodeError { opcode }));
+        }
+        assert_eq!(
+            decode(OpCode(0xE1FF)).unwrap_err().to_string(),
+            "unknown opcode E1FF"
+        );
+    }
+}
@@ -1,30 +1,34 @@
-use super::{cpu::Cpu, gpu::Gpu};
+use super::{cpu::Cpu, error::Chip8Error, gpu::Gpu, input::KeyWait};
 
-#[derive(Debug, Copy, Clone)]
+type Result<T> = std::result::Result<T, Chip8Error>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct OpCode(pub u16);
 impl OpCode {
     /// Fill registers v0 to vX inclusive.
     /// Sets I = I + X + 1
     /// The interpreter reads values from memory starting at location I into registers V0 through Vx.
-    pub fn fx65(cpu: &mut Cpu) {
-        let num_registers = OpCode::get_x(&cpu);
-        for x in 0..=num_registers {
-            let load_index = cpu.index_register + (x as u16);
-            cpu.registers[x as usize] = cpu.memory.ram[load_index as usize]
+    pub fn fx65(cpu: &mut Cpu, x: u8) -> Result<()> {
+        let count = x as usize + 1;
+        let range = cpu.index_range(count)?;
+        cpu.registers[..count].copy_from_slice(&cpu.memory.ram[range]);
+        if cpu.quirks.load_store_increments_i {
+            cpu.index_register = cpu.index_register.wrapping_add(count as u16);
         }
-        cpu.index_register += (num_registers + 1) as u16;
+        Ok(())
     }
 
     /// Store register vals v0 to vX inclusive in memory starting at address I.
     /// Sets I = I + X + 1
     /// Basically fx65 but instead of putting memory into registers, puts registers into memory.
-    pub fn fx55(cpu: &mut Cpu) {
-        let num_registers = OpCode::get_x(&cpu);
-        for x in 0..=num_registers {
-            let load_index = cpu.index_register + (x as u16);
-            cpu.memory.ram[load_index as usize] = cpu.registers[x as usize];
+    pub fn fx55(cpu: &mut Cpu, x: u8) -> Result<()> {
+        let count = x as usize + 1;
+        let range = cpu.index_range(count)?;
+        cpu.memory.ram[range].copy_from_slice(&cpu.registers[..count]);
+        if cpu.quirks.load_store_increments_i {
+            cpu.index_register = cpu.index_register.wrapping_add(count as u16);
         }
-        cpu.index_register += (num_registers + 1) as u16;
+        Ok(())
     }
 
     /// Store BCD of value in vX at addresses I, I+1, I+2
@@ -32,89 +36,99 @@ impl OpCode {
     /// Stores the binary-coded decimal representation of VX, with the hundreds digit
     /// in memory at location in I, the tens digit at location I+1,
     /// and the ones digit at location I+2.[24]
-    pub fn fx33(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
+    pub fn fx33(cpu: &mut Cpu, x: u8) -> Result<()> {
         let register = cpu.registers[x as usize];
-        let padded = format!("{:0>3}", register);
-        let a: u8 = padded.chars().nth(0).unwrap() as u8 - 48; // ascii '0' starts at decimal 48
-        let b: u8 = padded.chars().nth(1).unwrap() as u8 - 48;
-        let c: u8 = padded.chars().nth(2).unwrap() as u8 - 48;
-        let index = cpu.index_register as usize;
-        cpu.memory.ram[index] = a;
-        cpu.memory.ram[index + 1] = b;
-        cpu.memory.ram[index + 2] = c;
+        let range = cpu.index_range(3)?;
+        cpu.memory.ram[range].copy_from_slice(&[register / 100, register / 10 % 10, register % 10]);
+        Ok(())
+    }
+
+    /// XO-CHIP: Select the bitplanes Dxyn, 00E0 and the scrolls act on.
+    /// 0 selects neither, 1 the first, 2 the second and 3 both.
+    pub fn fn01(gpu: &mut Gpu, n: u8) {
+        gpu.selected_planes = n & 0b11;
+    }
+
+    /// XO-CHIP: Set I to the 16-bit address stored in the word after this instruction,
+    /// then step over that word.
+    pub fn f000(cpu: &mut Cpu) -> Result<()> {
+        let pc = cpu.program_counter;
+        let Some(&[high, low]) = cpu.memory.ram.get(pc as usize..pc as usize + 2) else {
+            return Err(Chip8Error::PcOutOfBounds { pc });
+        };
+        cpu.index_register = u16::from_be_bytes([high, low]);
+        cpu.program_counter = cpu.program_counter.wrapping_add(2);
+        Ok(())
+    }
+
+    /// SUPER-CHIP: Fill v0 to vX inclusive from the RPL user flags, the inverse of fx75.
+    pub fn fx85(cpu: &mut Cpu, x: u8) {
+        let count = x as usize + 1;
+        cpu.registers[..count].copy_from_slice(&cpu.rpl_flags[..count]);
+    }
+
+    /// SUPER-CHIP: Store v0 to vX inclusive in the RPL user flags.
+    /// On the HP-48 these survived after the interpreter exited, here they live as long as the Cpu.
+    pub fn fx75(cpu: &mut Cpu, x: u8) {
+        let count = x as usize + 1;
+        cpu.rpl_flags[..count].copy_from_slice(&cpu.registers[..count]);
+    }
+
+    /// SUPER-CHIP: Set I to the 8x10 sprite for the hex digit stored in register vX
+    pub fn fx30(cpu: &mut Cpu, x: u8) {
+        use crate::emu::mem::BIG_FONT_START;
+        let digit = cpu.registers[x as usize] & 0xF;
+        cpu.index_register = (BIG_FONT_START + digit as usize * 10) as u16;
     }
 
     /// Set I to memory address of the sprite data corresponding to hex digit stored in register vX
-    pub fn fx29(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let vx = &cpu.registers[x as usize];
-        cpu.index_register = *vx as u16;
+    pub fn fx29(cpu: &mut Cpu, x: u8) {
+        use crate::emu::mem::FONT_START;
+        // each glyph is 5 bytes, only the low nibble selects one
+        let digit = cpu.registers[x as usize] & 0xF;
+        cpu.index_register = (FONT_START + digit as usize * 5) as u16;
     }
 
     /// Add the value stored in register vX to register I
     /// Set I = I + Vx.
     /// The values of I and Vx are added, and the results are stored in I.
-    pub fn fx1e(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
+    pub fn fx1e(cpu: &mut Cpu, x: u8) {
         let vx = &cpu.registers[x as usize];
         let i = &cpu.index_register;
-        let new_i = (*vx) as u16 + i;
+        let new_i = i.wrapping_add(*vx as u16);
         cpu.index_register = new_i;
     }
 
     /// Set the sound timer to value of register vX
-    pub fn fx18(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
+    pub fn fx18(cpu: &mut Cpu, x: u8) {
         let vx = cpu.registers[x as usize];
-        cpu.sound_timer = vx;
+        cpu.memory.sound_timer.set(vx);
     }
 
     /// Set the delay timer to the value of register vX
-    pub fn fx15(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
+    pub fn fx15(cpu: &mut Cpu, x: u8) {
         let vx = cpu.registers[x as usize];
-        cpu.delay_timer = vx;
+        cpu.memory.delay_timer.set(vx);
     }
 
     /// Wait for a keypress and store the result in register vX
-    pub fn fx0a(cpu: &mut Cpu) {
-        // use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-        let x = OpCode::get_x(cpu);
-        //let _ = enable_raw_mode();
-        let pressed_value = cpu.memory.gpu.handle_events().unwrap();
-        //let _ = disable_raw_mode();
-        cpu.registers[x as usize] = pressed_value;
-    }
-
-    /// fx0a but presses the 'x' key
-    pub fn fx0a_test(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-
-        use ratatui::crossterm::event::KeyCode;
-
-        let k = KeyCode::Char('x').into();
-        let whatisit = cpu.memory.gpu.handle_key_event(k).unwrap();
-        cpu.registers[x as usize] = whatisit;
-        assert_eq!(13, whatisit); // make sure our [1-4,q-r,a-f,z-v] maps to [0 - 16]
+    /// The cpu halts in Cpu::step until a key has been pressed and released again, like the VIP.
+    pub fn fx0a(cpu: &mut Cpu, x: u8) {
+        cpu.key_wait = Some(KeyWait::new(x));
     }
 
     /// Store the current value of the delay timer in register vX
-    pub fn fx07(cpu: &mut Cpu) {
-        let delay_timer = cpu.delay_timer;
-        let x = OpCode::get_x(cpu);
-        cpu.registers[x as usize] = delay_timer;
+    pub fn fx07(cpu: &mut Cpu, x: u8) {
+        cpu.registers[x as usize] = cpu.memory.delay_timer.get();
     }
 
     /// Skip the following instruction if the key corresponding to
     /// the hex value currently stored in register vX is NOT pressed
-    pub fn exa1(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
+    pub fn exa1(cpu: &mut Cpu, x: u8) {
         let vx = cpu.registers[x as usize];
-        let pressed_value = cpu.memory.gpu.handle_events().unwrap();
-        if pressed_value != vx {
+        if !cpu.memory.pad.is_pressed(vx) {
             // skip instruction
-            cpu.program_counter += 2;
+            cpu.skip_next();
         } else {
             // dont skip
         }
@@ -122,103 +136,134 @@ impl OpCode {
 
     /// Skip the following instruction if the key corresponding to
     /// the hex value currently stored in register vX is pressed
-    pub fn ex9e(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
+    pub fn ex9e(cpu: &mut Cpu, x: u8) {
         let vx = cpu.registers[x as usize];
-        let pressed_value = cpu.memory.gpu.handle_events().unwrap();
-        if pressed_value == vx {
+        if cpu.memory.pad.is_pressed(vx) {
             // skip instruction
-            cpu.program_counter += 2;
+            cpu.skip_next();
         } else {
             // dont skip
-            // galus note: I think that program counter being automatically incremented may
-            // start to cause problems... future galus will find out soonTm.
+            // future galus: Cpu::step already moved the pc past this instruction,
+            // so skipping only needs to add another 2 (or 4 over an F000).
         }
     }
 
     /// Draw a sprite at position vX, vY with N bytes of sprite data starting at the address
     /// stored in I. Set vF to 01 if any set pixels are changed to unset, and 00 otherwise.
-    pub fn dxyn(cpu: &mut Cpu) {
-        let (_, x, y, n) = OpCode::into_tuple(&cpu.current_opcode);
-        let start = cpu.index_register as usize;
-        let end = start + (n as usize);
-        let sprite_data = &cpu.memory.ram[start..end];
+    /// SUPER-CHIP: Dxy0 draws a 16x16 sprite stored as 32 bytes, two per row.
+    /// XO-CHIP: each selected plane gets its own copy of the sprite data, one after the other.
+    pub fn dxyn(cpu: &mut Cpu, x: u8, y: u8, n: u8) -> Result<()> {
+        let (width, height) = (cpu.memory.gpu.width(), cpu.memory.gpu.height());
+        let (sprite_width, rows) = if n == 0 { (16, 16) } else { (8, n as usize) };
+        let bytes_per_row = sprite_width / 8;
+        let sprite_len = rows * bytes_per_row;
+        // the starting position always wraps around the screen
         let (vx, vy) = (
-            cpu.registers[x as usize] as usize,
-            cpu.registers[y as usize] as usize,
+            cpu.registers[x as usize] as usize % width,
+            cpu.registers[y as usize] as usize % height,
         );
-        let screen_offset = vy.wrapping_mul(crate::emu::gpu::SCREEN_WIDTH) + vx;
-        // for each sprite byte
-        for i in 0..n {
-            let screen_start = screen_offset + (i as usize) * 8;
-            let old_pixels = &mut cpu.memory.gpu.screen[screen_start..(screen_start + 8)];
-            let sprite_byte = sprite_data[i as usize];
-            let new_pixels: Vec<bool> = old_pixels
-                .iter()
-                .zip((0..8).map(|bit| (sprite_byte & (1 << (7 - bit))) != 0))
-                .map(|(&old, new)| old ^ new)
-                .collect();
-
-            // Check if pixels erased: any pixels were flipped from set to unset
-            if old_pixels
-                .iter()
-                .zip(new_pixels.iter())
-                .any(|(&old, &new)| old && !new)
-            {
-                cpu.registers[0xF] = 1;
+        let clip = cpu.quirks.clip_sprites;
+        let mut erased = false;
+        let planes = cpu.memory.gpu.selected_planes.count_ones() as usize;
+        let mut start = cpu.index_range(sprite_len * planes)?.start;
+        for plane in cpu.memory.gpu.planes_mut() {
+            let sprite_data = &cpu.memory.ram[start..start + sprite_len];
+            start += sprite_len;
+            for (row, row_bytes) in sprite_data.chunks(bytes_per_row).enumerate() {
+                let py = vy + row;
+                if clip && py >= height {
+                    break;
+                }
+                // read the row as one big-endian bit string, 8 or 16 pixels wide
+                let bits = row_bytes
+                    .iter()
+                    .fold(0u32, |bits, &byte| bits << 8 | byte as u32);
+                for bit in 0..sprite_width {
+                    let px = vx + bit;
+                    if clip && px >= width {
+                        break;
+                    }
+                    if bits & (1 << (sprite_width - 1 - bit)) == 0 {
+                        continue;
+                    }
+                    // pixels are xor'd, so drawing over a set pixel erases it
+                    let pixel = &mut plane[(py % height) * width + px % width];
+                    erased |= *pixel;
+                    *pixel = !*pixel;
+                }
             }
-
-            // Update the screen with new pixels
-            old_pixels.copy_from_slice(&new_pixels);
         }
+        cpu.registers[0xF] = erased as u8;
+        // the VIP only drew during the vertical blank interrupt
+        cpu.waiting_for_vblank = cpu.quirks.display_wait;
+        Ok(())
     }
 
     /// Set vX to a random number with a mask of NN
     /// Set Vx = random byte AND kk.
     /// The interpreter generates a random number from 0 to 255, which is then ANDed with the value kk. The results are stored in Vx. See instruction 8xy2 for more information on AND.
-    pub fn cxnn(cpu: &mut Cpu) {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let rng = rand::random::<u8>();
-        let masked_rng = (n2 << 4 | n3) & rng;
+    pub fn cxnn(cpu: &mut Cpu, x: u8, mask: u8) {
+        let rng = cpu.rng.next_byte();
+        let masked_rng = mask & rng;
         cpu.registers[x as usize] = masked_rng;
     }
 
     /// Jump to address NNN + v0
-    pub fn bnnn(cpu: &mut Cpu) {
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
-        let added_address = cpu.registers[0] as u16 + address;
+    /// With the jump_uses_vx quirk this is BXNN instead, jumping to XNN + vX
+    pub fn bnnn(cpu: &mut Cpu, address: u16) {
+        let x = if cpu.quirks.jump_uses_vx {
+            (address >> 8) as usize
+        } else {
+            0
+        };
+        let added_address = cpu.registers[x] as u16 + address;
         cpu.program_counter = added_address;
     }
 
     /// Store memory address NNN in register I
-    pub fn annn(cpu: &mut Cpu) {
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
+    pub fn annn(cpu: &mut Cpu, address: u16) {
         cpu.index_register = address;
     }
 
+    /// XO-CHIP: Load vX to vY inclusive from memory starting at I, without changing I.
+    /// The range runs backwards when x > y.
+    pub fn _5xy3(cpu: &mut Cpu, x: u8, y: u8) -> Result<()> {
+        let start = cpu.index_range(x.abs_diff(y) as usize + 1)?.start;
+        for (offset, register) in register_range(x, y).enumerate() {
+            cpu.registers[register] = cpu.memory.ram[start + offset];
+        }
+        Ok(())
+    }
+
+    /// XO-CHIP: Store vX to vY inclusive in memory starting at I, without changing I.
+    /// The range runs backwards when x > y.
+    pub fn _5xy2(cpu: &mut Cpu, x: u8, y: u8) -> Result<()> {
+        let start = cpu.index_range(x.abs_diff(y) as usize + 1)?.start;
+        for (offset, register) in register_range(x, y).enumerate() {
+            cpu.memory.ram[start + offset] = cpu.registers[register];
+        }
+        Ok(())
+    }
+
     /// Skip the following instruction if the value of register vX is not equal to the value of
     /// register vY.
-    pub fn _9xy0(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _9xy0(cpu: &mut Cpu, x: u8, y: u8) {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         if vx != vy {
-            cpu.program_counter += 2;
+            cpu.skip_next();
         }
     }
 
     /// Store the value of register vY shifted left one bit in register vX
     /// Set register vF to the most significant bit prior to the shift
     /// vY is unchanged
-    pub fn _8xye(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    /// Without the shift_uses_vy quirk vX is shifted in place instead
+    pub fn _8xye(cpu: &mut Cpu, x: u8, y: u8) {
+        let y = if cpu.quirks.shift_uses_vy { y } else { x };
         let vy = cpu.registers[y as usize];
         let msb_vy = (vy & 0b10000000) >> 7;
-        cpu.registers[0xF as usize] = msb_vy;
+        cpu.registers[0xF] = msb_vy;
         let shifted_vy = vy << 1;
         cpu.registers[x as usize] = shifted_vy
     }
@@ -227,9 +272,7 @@ impl OpCode {
     /// ... Vx = Vy - Vx, VF = NOT borrow
     /// ... Set VF to 00 if a borrow occurs
     /// ... Set VF to 01 if a borrow does not occur
-    pub fn _8xy7(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _8xy7(cpu: &mut Cpu, x: u8, y: u8) {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         let (diff, borrow) = {
@@ -242,21 +285,21 @@ impl OpCode {
         };
         cpu.registers[x as usize] = diff;
         if borrow {
-            cpu.registers[0xF as usize] = 0x00;
+            cpu.registers[0xF] = 0x00;
         } else {
-            cpu.registers[0xF as usize] = 0x01;
+            cpu.registers[0xF] = 0x01;
         }
     }
 
     /// Store the value of register VY shifted right one bit in register VX¹
     /// Set register VF to the least significant bit prior to the shift
     /// VY is unchanged
-    pub fn _8xy6(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    /// Without the shift_uses_vy quirk vX is shifted in place instead
+    pub fn _8xy6(cpu: &mut Cpu, x: u8, y: u8) {
+        let y = if cpu.quirks.shift_uses_vy { y } else { x };
         let vy = cpu.registers[y as usize];
         let lsb_vy = vy & 0b00000001;
-        cpu.registers[0xF as usize] = lsb_vy;
+        cpu.registers[0xF] = lsb_vy;
         let shifted_vy = vy >> 1;
         cpu.registers[x as usize] = shifted_vy
     }
@@ -265,9 +308,7 @@ impl OpCode {
     /// ... Vx = Vx - Vy, set VF = NOT borrow
     /// ... Set VF to 00 if a borrow occurs
     /// ... Set VF to 01 if a borrow does not occur
-    pub fn _8xy5(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _8xy5(cpu: &mut Cpu, x: u8, y: u8) {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         let (diff, borrow) = {
@@ -280,9 +321,9 @@ impl OpCode {
         };
         cpu.registers[x as usize] = diff;
         if borrow {
-            cpu.registers[0xF as usize] = 0x00;
+            cpu.registers[0xF] = 0x00;
         } else {
-            cpu.registers[0xF as usize] = 0x01;
+            cpu.registers[0xF] = 0x01;
         }
     }
 
@@ -290,9 +331,7 @@ impl OpCode {
     /// Set VF to 01 if a carry occurs
     /// Set VF to 00 if a carry does not occur
     //#[feature(bigint_helper_methods)]
-    pub fn _8xy4(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _8xy4(cpu: &mut Cpu, x: u8, y: u8) {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         let (sum, carry) = {
@@ -307,64 +346,60 @@ impl OpCode {
         cpu.registers[0xF] = carry as u8;
     }
 
-    /// 11 + 11 =>  3 + 3 = 6 = 110 , 111 + 111 = 7+7 = 14 = 1110 , overflow means lsb of larger
-    ///    type
+    // 11 + 11 =>  3 + 3 = 6 = 110 , 111 + 111 = 7+7 = 14 = 1110 , overflow means lsb of larger
+    //    type
 
     /// Set vX to vX XOR vY
-    pub fn _8xy3(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _8xy3(cpu: &mut Cpu, x: u8, y: u8) {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         cpu.registers[x as usize] = vx ^ vy;
+        if cpu.quirks.logic_resets_vf {
+            cpu.registers[0xF] = 0;
+        }
     }
 
     /// Set vX to vX AND vY
-    pub fn _8xy2(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _8xy2(cpu: &mut Cpu, x: u8, y: u8) {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         cpu.registers[x as usize] = vx & vy;
+        if cpu.quirks.logic_resets_vf {
+            cpu.registers[0xF] = 0;
+        }
     }
 
     /// Set vX to vX OR vY
-    pub fn _8xy1(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _8xy1(cpu: &mut Cpu, x: u8, y: u8) {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         cpu.registers[x as usize] = vx | vy;
+        if cpu.quirks.logic_resets_vf {
+            cpu.registers[0xF] = 0;
+        }
     }
 
     /// Store the value of register vY in register vX
-    pub fn _8xy0(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _8xy0(cpu: &mut Cpu, x: u8, y: u8) {
         let vy = cpu.registers[y as usize];
         cpu.registers[x as usize] = vy;
     }
 
     /// Add the value NN to register vX
-    pub fn _7xnn(cpu: &mut Cpu) {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let value = (n2 as u8) << 4 | n3 as u8;
-        let temp = cpu.registers[x as usize] + value;
+    pub fn _7xnn(cpu: &mut Cpu, x: u8, value: u8) {
+        // no carry flag, the sum just wraps
+        let temp = cpu.registers[x as usize].wrapping_add(value);
         cpu.registers[x as usize] = temp;
     }
 
     /// Store the number NN in register vX
-    pub fn _6xnn(cpu: &mut Cpu) {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let value = (n2 as u8) << 4 | n3 as u8;
+    pub fn _6xnn(cpu: &mut Cpu, x: u8, value: u8) {
         cpu.registers[x as usize] = value;
     }
 
     /// Skip the following instruction if the value of register vX is equal to the value of
     /// register vY.
-    pub fn _5xy0(cpu: &mut Cpu) {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    pub fn _5xy0(cpu: &mut Cpu, x: u8, y: u8) {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         if vx == vy {
@@ -373,49 +408,42 @@ impl OpCode {
             // future galus: we need to handle the execution w/ the program_counter
             // ... the index_register is for interacting with memory and other things
             // ... and +1 will go to next instruction, so we need to +2 instead
-            cpu.program_counter += 2;
+            cpu.skip_next();
         }
     }
 
     /// Skip the following instruction if the value of register vX is NOT equal to NN
-    pub fn _4xnn(cpu: &mut Cpu) {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let value = (n2 as u8) << 4 | n3 as u8;
+    pub fn _4xnn(cpu: &mut Cpu, x: u8, value: u8) {
         let vx = cpu.registers[x as usize];
         if vx != value {
-            cpu.program_counter += 2;
+            cpu.skip_next();
         }
     }
 
     /// Skip the following instruction if the value of register vX is equal to NN
-    pub fn _3xnn(cpu: &mut Cpu) {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let value = (n2 as u8) << 4 | n3 as u8;
+    pub fn _3xnn(cpu: &mut Cpu, x: u8, value: u8) {
         let vx = cpu.registers[x as usize];
         if vx == value {
-            cpu.program_counter += 2;
+            cpu.skip_next();
         }
     }
 
     /// Execute subroutine starting at address NNN
-    pub fn _2nnn(cpu: &mut Cpu) {
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
+    /// The program counter already points past the call, so that is what gets pushed for 00EE.
+    pub fn _2nnn(cpu: &mut Cpu, address: u16) -> Result<()> {
+        cpu.push_stack(cpu.program_counter)?;
         cpu.program_counter = address;
+        Ok(())
     }
 
     /// Jump to address NNN
-    pub fn _1nnn(cpu: &mut Cpu) {
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
+    pub fn _1nnn(cpu: &mut Cpu, address: u16) {
         cpu.program_counter = address;
     }
 
-    /// Execute machine language subroutine at address NNN
-    pub fn _0nnn(cpu: &mut Cpu) {
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
-        println!("address d{:?}, x{:x?}", address, address);
+    /// Execute machine language subroutine at address NNN.
+    /// There is no 1802 to run it on, so this is a plain jump, which `analysis` warns about.
+    pub fn _0nnn(cpu: &mut Cpu, address: u16) {
         // Figure out if this NNN is BCD'd or if its the bits sequentially
         // where 0000 1111     0000 1011     0000 0111 implies -> 1111 1011 0111
         //              15            11             6         ->    E    B    6
@@ -425,37 +453,62 @@ impl OpCode {
         cpu.program_counter = address;
     }
 
+    /// SUPER-CHIP: Switch to the 128x64 high resolution mode
+    pub fn _00ff(gpu: &mut Gpu) {
+        gpu.set_hires(true);
+    }
+
+    /// SUPER-CHIP: Switch back to the 64x32 low resolution mode
+    pub fn _00fe(gpu: &mut Gpu) {
+        gpu.set_hires(false);
+    }
+
+    /// SUPER-CHIP: Exit the interpreter
+    pub fn _00fd(cpu: &mut Cpu) {
+        cpu.exited = true;
+    }
+
+    /// SUPER-CHIP: Scroll the display 4 pixels left
+    pub fn _00fc(gpu: &mut Gpu) {
+        gpu.scroll_left(4);
+    }
+
+    /// SUPER-CHIP: Scroll the display 4 pixels right
+    pub fn _00fb(gpu: &mut Gpu) {
+        gpu.scroll_right(4);
+    }
+
     /// Clear the screen
     pub fn _00e0(gpu: &mut Gpu) {
-        gpu.screen = [false; 64 * 32];
+        gpu.clear();
     }
 
-    /// Return from a subroutine
-    pub fn _00ee(_emu: &Cpu) {
-        return;
+    /// SUPER-CHIP: Scroll the display down N pixels
+    pub fn _00cn(gpu: &mut Gpu, n: u8) {
+        gpu.scroll_down(n as usize);
     }
 
-    /// Returns current opcodes 2nd nibble
-    pub fn get_x(cpu: &Cpu) -> u8 {
-        //let op = cpu.current_opcode;
-        //let (_, x, _, _) = op.into_tuple();
-        //x
-        cpu.current_opcode.into_tuple().1
+    /// Return from a subroutine
+    /// Pops the return address pushed by 2nnn back into the program counter.
+    pub fn _00ee(cpu: &mut Cpu) -> Result<()> {
+        cpu.program_counter = cpu.pop_stack()?;
+        Ok(())
     }
+}
 
-    /// Returns current opcodes 3rd nibble
-    pub fn get_y(cpu: &Cpu) -> u8 {
-        cpu.current_opcode.into_tuple().2
-    }
+/// Register indices from `x` to `y` inclusive, counting down if `x` is the larger one
+fn register_range(x: u8, y: u8) -> impl Iterator<Item = usize> {
+    let (x, y) = (x as usize, y as usize);
+    (0..=x.abs_diff(y)).map(move |offset| if x <= y { x + offset } else { x - offset })
 }
 
 pub trait Nibbles {
-    fn into_tuple(&self) -> (u8, u8, u8, u8);
+    fn into_tuple(self) -> (u8, u8, u8, u8);
     // fn into_vec(&self) -> Vec<u8>;
 }
 
 impl Nibbles for OpCode {
-    fn into_tuple(&self) -> (u8, u8, u8, u8) {
+    fn into_tuple(self) -> (u8, u8, u8, u8) {
         (
             ((0xF000 & self.0) >> 12) as u8,
             ((0x0F00 & self.0) >> 8) as u8,
@@ -1,9 +1,10 @@
 use crate::emu::{Gpu, Keypad, Timer};
 
 /* Chip8 Memory layout
-0x000-0x04F - Chip 8 interpreter (contains font set in emu)       0 -   79
-0x050-0x0A0 - Used for the built in 4x5 pixel font set (0-F)    080 -  160
+0x000-0x04F - Built in 4x5 pixel font set (0-F), see FONTS         0 -   79
+0x050-0x0EF - SUPER-CHIP 8x10 pixel font set (0-F), see BIG_FONTS 080 -  239
 0x200-0xFFF - Program ROM and work RAM                          512 - 4096
+0x1000-0xFFFF - XO-CHIP only, see XO_RAM_SIZE                  4096 - 65535
 
 0x200-0xE8F
 "final 352 bytes of memory are reserved for “variables and display refresh"
@@ -11,15 +12,26 @@ https://github.com/mattmikolay/chip-8/wiki/CHIP%E2%80%908-Technical-Reference#re
 */
 
 pub const RAM_SIZE: usize = 4096;
-pub const ROM_MAX_SIZE: usize = RAM_SIZE - 512;
+/// XO-CHIP addresses all 64KiB, reaching past 0xFFF with F000 nnnn
+pub const XO_RAM_SIZE: usize = 0x10000;
+/// Where programs are loaded and start running
+pub const PROGRAM_START: u16 = 0x200;
+/// Where programs for the ETI 660 are loaded instead
+pub const ETI_660_START: u16 = 0x600;
+/// Largest rom a 4KiB machine can run from PROGRAM_START
+pub const ROM_MAX_SIZE: usize = RAM_SIZE - PROGRAM_START as usize;
+/// Where FONTS is loaded, Fx29 points I at `FONT_START + digit * 5`
+pub const FONT_START: usize = 0x000;
+/// Where BIG_FONTS is loaded, Fx30 points I at `BIG_FONT_START + digit * 10`
+pub const BIG_FONT_START: usize = 0x050;
 
 #[derive(Debug)]
 pub struct Memory {
     pub delay_timer: Timer,
     pub gpu: Gpu,
     pub pad: Keypad,
-    pub ram: [u8; RAM_SIZE],
-    pub rom: Vec<u8>,
+    /// RAM_SIZE bytes, or XO_RAM_SIZE once `Emulator::set_preset` picks XO-CHIP
+    pub ram: Vec<u8>,
     pub sound_timer: Timer,
 }
 
@@ -29,21 +41,19 @@ impl Default for Memory {
             delay_timer: Timer::new(),
             gpu: Gpu::new(),
             pad: Keypad::new(),
-            ram: [0; RAM_SIZE],
-            rom: vec![0; ROM_MAX_SIZE],
+            ram: vec![0; RAM_SIZE],
             sound_timer: Timer::new(),
         }
     }
 }
 
 impl Memory {
-    pub fn new(dt: Timer, gpu: Gpu, pad: Keypad, rom: Vec<u8>, st: Timer) -> Self {
+    pub fn new(dt: Timer, gpu: Gpu, pad: Keypad, st: Timer) -> Self {
         Self {
             delay_timer: dt,
-            ram: [0; 4096],
+            ram: vec![0; RAM_SIZE],
             gpu,
             pad,
-            rom,
             sound_timer: st,
         }
     }
@@ -101,3 +111,24 @@ pub const FONTS: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+
+/// SUPER-CHIP 8x10 font for Fx30, one byte per row.
+/// SCHIP 1.1 only shipped 0-9, A-F are the glyphs Octo added.
+pub const BIG_FONTS: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
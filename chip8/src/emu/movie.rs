@@ -0,0 +1,366 @@
+// Input movies: the keypad changes of a run and the seed Cxnn started from, enough to replay the
+// run exactly given the same rom and flags. Movies are plain text so they can sit in a bug report.
+//
+//   chip8-movie 1
+//   seed 1234
+//   frames 600
+//   input 12 0x0010
+//
+// `input F K` sets the keypad to the bitmask K (key 0 in the lowest bit) after frame F,
+// and the replay stops once `frames` frames have been shown.
+use std::{fmt, fs, path::Path, str::FromStr, time::Duration};
+
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Report, Result,
+};
+
+use super::{
+    error::Chip8Error,
+    frontend::{Command, Frontend},
+    input::Keypad,
+    Emulator,
+};
+
+/// Bump whenever the movie format changes
+pub const VERSION: u32 = 1;
+
+/// The keypad after `frame` frames
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Input {
+    pub frame: u64,
+    pub keys: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// What Cxnn's generator was seeded with at power on
+    pub seed: u64,
+    /// Length of the run in frames
+    pub frames: u64,
+    /// Keypad changes in frame order
+    pub inputs: Vec<Input>,
+}
+
+impl Movie {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            frames: 0,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        fs::read_to_string(path)
+            .wrap_err_with(|| format!("reading {}", path.display()))?
+            .parse()
+            .wrap_err_with(|| format!("loading movie {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string()).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "chip8-movie {VERSION}")?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "frames {}", self.frames)?;
+        for input in &self.inputs {
+            writeln!(f, "input {} {:#06x}", input.frame, input.keys)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = Report;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()));
+        match lines.next() {
+            Some((_, header)) if header == format!("chip8-movie {VERSION}") => {}
+            Some((_, header)) if header.starts_with("chip8-movie ") => {
+                bail!("movie is `{header}`, this build reads version {VERSION}")
+            }
+            _ => bail!("not a chip8 movie, the first line should be `chip8-movie {VERSION}`"),
+        }
+
+        let mut movie = Movie::new(0);
+        for (number, line) in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parse = |field: &str| -> Result<u64> {
+                let parsed = match field.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => field.parse(),
+                };
+                parsed.map_err(|_| eyre!("line {number}: `{field}` is not a number"))
+            };
+            match fields.as_slice() {
+                [] => {}
+                ["seed", seed] => movie.seed = parse(seed)?,
+                ["frames", frames] => movie.frames = parse(frames)?,
+                ["input", frame, keys] => {
+                    let input = Input {
+                        frame: parse(frame)?,
+                        keys: u16::try_from(parse(keys)?)
+                            .map_err(|_| eyre!("line {number}: keys must fit in 16 bits"))?,
+                    };
+                    if movie
+                        .inputs
+                        .last()
+                        .is_some_and(|last| last.frame > input.frame)
+                    {
+                        bail!("line {number}: inputs must be in frame order");
+                    }
+                    movie.inputs.push(input);
+                }
+                _ => bail!("line {number}: can't read `{line}`"),
+            }
+        }
+        Ok(movie)
+    }
+}
+
+/// Passes everything through to `inner` while writing down each keypad change.
+/// Only quitting and saving slots get through, the movie couldn't follow rewinding, loading
+/// or the debugger.
+pub struct MovieRecorder<'a> {
+    inner: &'a mut dyn Frontend,
+    movie: Movie,
+    last_keys: u16,
+}
+
+impl<'a> MovieRecorder<'a> {
+    /// Record `emu`'s run on top of `inner`, starting now. The replay reseeds Cxnn's generator,
+    /// so it has to be a seeded one that hasn't handed out any numbers yet.
+    pub fn new(inner: &'a mut dyn Frontend, emu: &Emulator) -> Result<Self> {
+        let seed = match emu.cpu.rng.position() {
+            Some((seed, 0)) => seed,
+            Some(_) => bail!("can't record a movie once Cxnn has used random numbers"),
+            None => bail!("can't record a movie, the random number source has no seed"),
+        };
+        Ok(Self {
+            inner,
+            movie: Movie::new(seed),
+            last_keys: 0,
+        })
+    }
+
+    /// The recording so far
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
+
+impl Frontend for MovieRecorder<'_> {
+    fn present(&mut self, emu: &Emulator) -> Result<(), Chip8Error> {
+        self.movie.frames += 1;
+        self.inner.present(emu)
+    }
+
+    fn poll_input(
+        &mut self,
+        pad: &mut Keypad,
+        timeout: Duration,
+    ) -> Result<Vec<Command>, Chip8Error> {
+        let mut commands = self.inner.poll_input(pad, timeout)?;
+        if pad.bits() != self.last_keys {
+            self.last_keys = pad.bits();
+            self.movie.inputs.push(Input {
+                frame: self.movie.frames,
+                keys: self.last_keys,
+            });
+        }
+        commands.retain(|command| matches!(command, Command::Quit | Command::SaveSlot(_)));
+        Ok(commands)
+    }
+
+    fn notify(&mut self, message: &str) {
+        self.inner.notify(message)
+    }
+}
+
+/// Shows frames through `inner` but takes the keypad from a movie, quitting when it ends.
+/// Only `inner`'s quit gets through, anything else would make the run differ from the movie.
+pub struct MoviePlayer<'a> {
+    inner: &'a mut dyn Frontend,
+    movie: Movie,
+    presented: u64,
+    next_input: usize,
+    /// What the real keyboard is doing, ignored
+    live_pad: Keypad,
+}
+
+impl<'a> MoviePlayer<'a> {
+    /// Seed `emu` the way the movie was recorded and play it on top of `inner`
+    pub fn new(inner: &'a mut dyn Frontend, movie: Movie, emu: &mut Emulator) -> Self {
+        emu.cpu.seed_rng(movie.seed);
+        Self {
+            inner,
+            movie,
+            presented: 0,
+            next_input: 0,
+            live_pad: Keypad::default(),
+        }
+    }
+
+    /// The whole movie has been shown
+    pub fn finished(&self) -> bool {
+        self.presented >= self.movie.frames
+    }
+}
+
+impl Frontend for MoviePlayer<'_> {
+    fn present(&mut self, emu: &Emulator) -> Result<(), Chip8Error> {
+        self.presented += 1;
+        self.inner.present(emu)
+    }
+
+    fn poll_input(
+        &mut self,
+        pad: &mut Keypad,
+        timeout: Duration,
+    ) -> Result<Vec<Command>, Chip8Error> {
+        let mut commands = self.inner.poll_input(&mut self.live_pad, timeout)?;
+        commands.retain(|command| *command == Command::Quit);
+        while let Some(input) = self.movie.inputs.get(self.next_input) {
+            if input.frame > self.presented {
+                break;
+            }
+            *pad = Keypad::from_bits(input.keys);
+            self.next_input += 1;
+        }
+        if self.finished() {
+            commands.push(Command::Quit);
+        }
+        Ok(commands)
+    }
+
+    fn notify(&mut self, message: &str) {
+        self.inner.notify(message)
+    }
+}
+
+#[cfg(test)]
+mod movietests {
+    use super::{Input, Movie, MoviePlayer, MovieRecorder};
+    use crate::emu::{
+        error::Chip8Error,
+        frontend::{headless::HeadlessFrontend, Command, Frontend},
+        input::Keypad,
+        random::FixedSequence,
+        Emulator,
+    };
+
+    type Result<T> = std::result::Result<T, Chip8Error>;
+    use std::time::Duration;
+
+    /// Presses key 0 for a few frames, then quits after frame 8
+    struct Player {
+        presented: u64,
+    }
+
+    impl Frontend for Player {
+        fn present(&mut self, _emu: &Emulator) -> Result<()> {
+            self.presented += 1;
+            Ok(())
+        }
+
+        fn poll_input(&mut self, pad: &mut Keypad, _timeout: Duration) -> Result<Vec<Command>> {
+            match self.presented {
+                2 => pad.press(0),
+                5 => pad.release(0),
+                6 => return Ok(vec![Command::Rewind]),
+                8 => return Ok(vec![Command::Quit]),
+                _ => {}
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    /// Sums random numbers into V1 and counts frames with key 0 held in V4
+    fn emulator() -> Emulator {
+        let mut emu = Emulator::new();
+        let rom = [
+            0xC0, 0xFF, // RND V0, 0xFF
+            0x81, 0x04, // ADD V1, V0
+            0xE3, 0x9E, // SKP V3
+            0x12, 0x00, // JP 0x200
+            0x74, 0x01, // ADD V4, 1
+            0x12, 0x00, // JP 0x200
+        ];
+        emu.cpu.memory.ram[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        emu
+    }
+
+    #[test]
+    fn test_replay_reproduces_the_run() {
+        let mut recorded = emulator();
+        recorded.cpu.seed_rng(1234);
+        let mut player = Player { presented: 0 };
+        let mut recorder = MovieRecorder::new(&mut player, &recorded).unwrap();
+        recorded.run(&mut recorder, 5, Duration::ZERO).unwrap();
+        let movie = recorder.movie().clone();
+        assert_eq!(movie.frames, 8);
+        assert_eq!(
+            movie.inputs,
+            [Input { frame: 2, keys: 1 }, Input { frame: 5, keys: 0 }]
+        );
+        assert!(recorded.cpu.registers[4] > 0);
+
+        let movie: Movie = movie.to_string().parse().unwrap();
+        let mut replayed = emulator();
+        let mut headless = HeadlessFrontend::new(u64::MAX);
+        let mut replay = MoviePlayer::new(&mut headless, movie, &mut replayed);
+        replayed.run(&mut replay, 5, Duration::ZERO).unwrap();
+        assert!(replay.finished());
+        assert_eq!(replayed.save_state(), recorded.save_state());
+    }
+
+    #[test]
+    fn test_recorder_needs_a_fresh_seed() {
+        let mut headless = HeadlessFrontend::new(1);
+        let mut emu = emulator();
+        emu.cpu.set_rng(FixedSequence::new([1]));
+        assert!(MovieRecorder::new(&mut headless, &emu).is_err());
+
+        emu.cpu.seed_rng(3);
+        emu.cpu.rng.next_byte();
+        assert!(MovieRecorder::new(&mut headless, &emu).is_err());
+        emu.cpu.seed_rng(3);
+        let recorder = MovieRecorder::new(&mut headless, &emu).unwrap();
+        assert_eq!(recorder.movie().seed, 3);
+    }
+
+    #[test]
+    fn test_parse_movie() {
+        let movie: Movie = "chip8-movie 1\nseed 7\nframes 3\n\ninput 1 0x8001\n"
+            .parse()
+            .unwrap();
+        assert_eq!(movie.seed, 7);
+        assert_eq!(movie.frames, 3);
+        assert_eq!(
+            movie.inputs,
+            [Input {
+                frame: 1,
+                keys: 0x8001
+            }]
+        );
+
+        let err = "chip8-movie 2\n".parse::<Movie>().unwrap_err();
+        assert!(err.to_string().contains("version 1"));
+        let err = "chip8-movie 1\ninput 4 0\ninput 2 0\n"
+            .parse::<Movie>()
+            .unwrap_err();
+        assert!(err.to_string().contains("line 3"));
+        assert!("P1\n".parse::<Movie>().is_err());
+    }
+}
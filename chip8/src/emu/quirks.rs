@@ -0,0 +1,120 @@
+// Behaviours that differ between chip8 interpreters.
+use std::{fmt, str::FromStr};
+
+use super::mem::{RAM_SIZE, XO_RAM_SIZE};
+
+/// Toggles for the instructions whose meaning changed between interpreters.
+/// https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift vY into vX. Off: vX is shifted in place and vY is ignored.
+    pub shift_uses_vy: bool,
+    /// Fx55/Fx65 leave I pointing past the last register. Off: I is unchanged.
+    pub load_store_increments_i: bool,
+    /// Bnnn is read as BXnn and jumps to xnn + vX. Off: jumps to nnn + v0.
+    pub jump_uses_vx: bool,
+    /// Dxyn cuts sprites off at the screen edges. Off: they wrap around to the other side.
+    pub clip_sprites: bool,
+    /// 8xy1/8xy2/8xy3 reset vF to 0.
+    pub logic_resets_vf: bool,
+    /// Dxyn waits for the next 60Hz vblank, so at most one sprite is drawn per frame.
+    pub display_wait: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Preset::CosmacVip.quirks()
+    }
+}
+
+/// Named quirk profiles for the interpreters roms were written against.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Preset {
+    /// The original 1977 interpreter on the RCA COSMAC VIP
+    CosmacVip,
+    /// CHIP-48 on the HP-48 calculators
+    Chip48,
+    /// SUPER-CHIP 1.1, also on the HP-48
+    SuperChip,
+    /// Octo's XO-CHIP extension
+    XoChip,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [
+        Preset::CosmacVip,
+        Preset::Chip48,
+        Preset::SuperChip,
+        Preset::XoChip,
+    ];
+
+    /// The name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::CosmacVip => "vip",
+            Preset::Chip48 => "chip48",
+            Preset::SuperChip => "schip",
+            Preset::XoChip => "xochip",
+        }
+    }
+
+    /// Bytes of memory the platform has
+    pub fn ram_size(self) -> usize {
+        match self {
+            Preset::XoChip => XO_RAM_SIZE,
+            _ => RAM_SIZE,
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Preset::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                clip_sprites: true,
+                logic_resets_vf: true,
+                display_wait: true,
+            },
+            Preset::Chip48 | Preset::SuperChip => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                clip_sprites: true,
+                logic_resets_vf: false,
+                display_wait: false,
+            },
+            Preset::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                clip_sprites: false,
+                logic_resets_vf: false,
+                display_wait: false,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Preset::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Preset::ALL.iter().map(|preset| preset.name()).collect();
+                format!(
+                    "unknown quirk preset `{name}`, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
@@ -0,0 +1,111 @@
+// Where Cxnn's random numbers come from. Swappable so runs can be replayed and tests can pick the
+// numbers a rom sees.
+use std::fmt;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// A stream of random bytes for Cxnn
+pub trait RandomSource: fmt::Debug {
+    fn next_byte(&mut self) -> u8;
+
+    /// The seed and how far into its stream the source has got, for sources that
+    /// `SeededRandom::resume` can put back. Save states keep this so Cxnn carries on with the
+    /// same numbers after a load.
+    fn position(&self) -> Option<(u64, u128)> {
+        None
+    }
+}
+
+/// The default source, the same bytes for the same seed on every platform and rand version,
+/// which replaying a movie depends on
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    rng: ChaCha8Rng,
+    seed: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            seed,
+        }
+    }
+
+    /// Pick up `seed`'s stream where `position` left it, `word_pos` being ChaCha's count of
+    /// 32-bit words handed out
+    pub fn resume(seed: u64, word_pos: u128) -> Self {
+        let mut random = Self::new(seed);
+        random.rng.set_word_pos(word_pos);
+        random
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self) -> u8 {
+        self.rng.gen()
+    }
+
+    fn position(&self) -> Option<(u64, u128)> {
+        Some((self.seed, self.rng.get_word_pos()))
+    }
+}
+
+/// Hands out `bytes` in order and starts over at the end, for tests that need to know what
+/// Cxnn will produce
+#[derive(Debug, Clone)]
+pub struct FixedSequence {
+    bytes: Vec<u8>,
+    next: usize,
+}
+
+impl FixedSequence {
+    /// `bytes` must not be empty
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        let bytes = bytes.into();
+        assert!(!bytes.is_empty(), "a FixedSequence needs at least one byte");
+        Self { bytes, next: 0 }
+    }
+}
+
+impl RandomSource for FixedSequence {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.bytes[self.next];
+        self.next = (self.next + 1) % self.bytes.len();
+        byte
+    }
+}
+
+#[cfg(test)]
+mod randomtests {
+    use super::{FixedSequence, RandomSource, SeededRandom};
+
+    #[test]
+    fn test_sources_repeat() {
+        let mut fixed = FixedSequence::new([1, 2, 3]);
+        let bytes: Vec<u8> = (0..5).map(|_| fixed.next_byte()).collect();
+        assert_eq!(bytes, [1, 2, 3, 1, 2]);
+
+        let (mut a, mut b) = (SeededRandom::new(42), SeededRandom::new(42));
+        let a: Vec<u8> = (0..16).map(|_| a.next_byte()).collect();
+        let b: Vec<u8> = (0..16).map(|_| b.next_byte()).collect();
+        assert_eq!(a, b);
+        let mut other = SeededRandom::new(43);
+        let other: Vec<u8> = (0..16).map(|_| other.next_byte()).collect();
+        assert_ne!(a, other);
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut random = SeededRandom::new(42);
+        random.next_byte();
+        let (seed, word_pos) = random.position().unwrap();
+        assert_eq!(seed, 42);
+        let mut resumed = SeededRandom::resume(seed, word_pos);
+        let a: Vec<u8> = (0..16).map(|_| random.next_byte()).collect();
+        let b: Vec<u8> = (0..16).map(|_| resumed.next_byte()).collect();
+        assert_eq!(a, b);
+        assert_eq!(FixedSequence::new([1]).position(), None);
+    }
+}
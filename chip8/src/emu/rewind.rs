@@ -0,0 +1,230 @@
+// Rewinding gameplay. Every few frames the machine is snapshotted with `save_state`; only the
+// newest snapshot is kept whole; each older one is stored as the difference from the snapshot
+// after it. Most of memory doesn't change between frames, so a difference is a few bytes.
+use std::collections::VecDeque;
+
+/// Snapshot every this many frames unless told otherwise
+pub const DEFAULT_INTERVAL: u32 = 2;
+/// Bytes of snapshots kept unless told otherwise
+pub const DEFAULT_BUDGET: usize = 4 * 1024 * 1024;
+
+/// How to get from one snapshot back to the one before it
+#[derive(Debug)]
+enum Delta {
+    /// Runs of bytes that differ, as `skip: u32, len: u32, len bytes` records of the XOR
+    /// of the two snapshots
+    Xor(Vec<u8>),
+    /// The snapshots differ in length, e.g. across a resolution switch, so keep it whole
+    Full(Vec<u8>),
+}
+
+impl Delta {
+    fn len(&self) -> usize {
+        match self {
+            Delta::Xor(bytes) | Delta::Full(bytes) => bytes.len(),
+        }
+    }
+}
+
+/// A bounded history of snapshots, newest last
+#[derive(Debug)]
+pub struct Rewind {
+    /// Frames between snapshots
+    interval: u32,
+    /// Bytes the snapshots may use in total, 0 turns rewinding off
+    budget: usize,
+    frames: u32,
+    /// The newest snapshot, in full
+    latest: Option<Vec<u8>>,
+    /// `deltas[i]` turns snapshot i + 1 back into snapshot i, the last one applies to `latest`
+    deltas: VecDeque<Delta>,
+    used: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL, DEFAULT_BUDGET)
+    }
+}
+
+impl Rewind {
+    pub fn new(interval: u32, budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            frames: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+            used: 0,
+        }
+    }
+
+    /// Called once per emulated frame, true when a snapshot is due
+    pub fn frame_finished(&mut self) -> bool {
+        if self.budget == 0 {
+            return false;
+        }
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            return true;
+        }
+        false
+    }
+
+    /// Add the newest snapshot, dropping the oldest ones once over budget
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(latest) = self.latest.take() {
+            self.used -= latest.len();
+            let delta = diff(&state, latest);
+            self.used += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.used += state.len();
+        self.latest = Some(state);
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.used -= oldest.len(),
+                None => {
+                    // a single snapshot bigger than the budget
+                    self.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Take the newest snapshot, the one before it becomes the newest
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let latest = self.latest.take()?;
+        self.used -= latest.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            self.used -= delta.len();
+            let previous = apply(&latest, delta);
+            self.used += previous.len();
+            self.latest = Some(previous);
+        }
+        self.frames = 0;
+        Some(latest)
+    }
+
+    /// Snapshots held
+    pub fn len(&self) -> usize {
+        self.latest.iter().len() + self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Bytes held, never more than the budget
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+}
+
+/// The delta that turns `newer` back into `older`
+fn diff(newer: &[u8], older: Vec<u8>) -> Delta {
+    if newer.len() != older.len() {
+        return Delta::Full(older);
+    }
+    let mut records = Vec::new();
+    let mut i = 0;
+    let mut last_end = 0;
+    while i < newer.len() {
+        if newer[i] == older[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < newer.len() && newer[i] != older[i] {
+            i += 1;
+        }
+        records.extend(((start - last_end) as u32).to_le_bytes());
+        records.extend(((i - start) as u32).to_le_bytes());
+        records.extend((start..i).map(|j| newer[j] ^ older[j]));
+        last_end = i;
+    }
+    Delta::Xor(records)
+}
+
+fn apply(newer: &[u8], delta: Delta) -> Vec<u8> {
+    let records = match delta {
+        Delta::Full(older) => return older,
+        Delta::Xor(records) => records,
+    };
+    let mut older = newer.to_vec();
+    let mut pos = 0;
+    let mut rest = records.as_slice();
+    while !rest.is_empty() {
+        let skip = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let len = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        pos += skip;
+        for (byte, x) in older[pos..pos + len].iter_mut().zip(&rest[8..8 + len]) {
+            *byte ^= x;
+        }
+        pos += len;
+        rest = &rest[8 + len..];
+    }
+    older
+}
+
+#[cfg(test)]
+mod rewindtests {
+    use super::{apply, diff, Delta, Rewind};
+
+    #[test]
+    fn test_delta_round_trip() {
+        let older = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        let newer = vec![0, 9, 9, 3, 4, 5, 6, 8];
+        let delta = diff(&newer, older.clone());
+        // two runs of changes, 8 bytes of header each
+        assert_eq!(delta.len(), 8 + 2 + 8 + 1);
+        assert_eq!(apply(&newer, delta), older);
+
+        let grown = vec![0; 12];
+        let delta = diff(&grown, older.clone());
+        assert!(matches!(delta, Delta::Full(_)));
+        assert_eq!(apply(&grown, delta), older);
+    }
+
+    #[test]
+    fn test_rewind_returns_newest_first() {
+        let mut rewind = Rewind::new(1, 1024);
+        for frame in 0..5u8 {
+            assert!(rewind.frame_finished());
+            let mut state = vec![0; 64];
+            state[frame as usize] = frame + 1;
+            rewind.push(state);
+        }
+        assert_eq!(rewind.len(), 5);
+        for frame in (0..5u8).rev() {
+            let state = rewind.pop().unwrap();
+            assert_eq!(state[frame as usize], frame + 1);
+        }
+        assert!(rewind.pop().is_none());
+        assert_eq!(rewind.used(), 0);
+    }
+
+    #[test]
+    fn test_rewind_stays_in_budget() {
+        let mut rewind = Rewind::new(1, 100);
+        for frame in 0..50u8 {
+            rewind.push(vec![frame; 40]);
+            assert!(rewind.used() <= 100);
+        }
+        // 40 for the newest, each older one costs a 48 byte delta
+        assert_eq!(rewind.len(), 2);
+        assert_eq!(rewind.pop().unwrap(), vec![49; 40]);
+        assert_eq!(rewind.pop().unwrap(), vec![48; 40]);
+
+        let mut off = Rewind::new(1, 0);
+        assert!(!off.frame_finished());
+    }
+}
@@ -0,0 +1,185 @@
+// What we know about particular roms, keyed by the SHA-1 of the rom file so a renamed or
+// re-downloaded copy is still recognised. roms/romdb.toml is built into the binary and more
+// entries can be loaded on top of it from a file in the same format:
+//
+//   [b9272ae1acdaaa79ab649f6b48b72088ca2b1d74]
+//   title = "Maze"
+//   author = "David Winter"
+//   platform = "CHIP-8"
+//   quirks = "vip"
+//   tick_rate = 9
+//   keys = "none, it draws by itself"
+//
+// Only the title is required.
+use std::{collections::HashMap, fs, path::Path};
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use serde::{Deserialize, Deserializer};
+
+use super::quirks::Preset;
+
+/// The database shipped with the emulator
+const BUNDLED: &str = include_str!("../../roms/romdb.toml");
+
+/// One rom's entry
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    /// The machine the rom was written for, e.g. `SUPER-CHIP 1.1`
+    pub platform: Option<String>,
+    /// The quirk preset it runs correctly under
+    #[serde(default, deserialize_with = "preset")]
+    pub quirks: Option<Preset>,
+    /// Instructions per frame it was tuned for
+    pub tick_rate: Option<usize>,
+    /// What the keys do, the keypad layout varies from rom to rom
+    pub keys: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RomDb {
+    /// Entries by lower case hex SHA-1
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDb {
+    /// The database built into the binary
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("roms/romdb.toml is checked by the tests")
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let roms: HashMap<String, RomInfo> =
+            basic_toml::from_str(text).map_err(|err| eyre!("{err}"))?;
+        for hash in roms.keys() {
+            if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(eyre!("`{hash}` is not a SHA-1, expected 40 hex digits"));
+            }
+        }
+        let roms = roms
+            .into_iter()
+            .map(|(hash, info)| (hash.to_ascii_lowercase(), info))
+            .collect();
+        Ok(Self { roms })
+    }
+
+    /// Add the entries of the database at `path`, replacing any for the same rom
+    pub fn extend_from(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).wrap_err_with(|| format!("reading {}", path.display()))?;
+        let other = Self::parse(&text).wrap_err_with(|| format!("in {}", path.display()))?;
+        self.roms.extend(other.roms);
+        Ok(())
+    }
+
+    /// The entry for `rom`, if it's a rom we know
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&sha1(rom))
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+/// The SHA-1 of `rom` as lower case hex, the key of its entry
+pub fn sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+/// Reads `quirks = "schip"` with the names `--quirks` takes
+fn preset<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Preset>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod romdbtests {
+    use super::{sha1, RomDb, RomInfo};
+    use crate::emu::quirks::Preset;
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(sha1(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    }
+
+    #[test]
+    fn test_parse() {
+        let db = RomDb::parse(
+            r#"
+            [A9993E364706816ABA3E25717850C26C9CD0D89D]
+            title = "abc"
+            quirks = "schip"
+            tick_rate = 30
+            keys = "7 and 9 move"
+
+            [da39a3ee5e6b4b0d3255bfef95601890afd80709]
+            title = "nothing"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(db.len(), 2);
+        assert_eq!(
+            db.lookup(b"abc"),
+            Some(&RomInfo {
+                title: "abc".to_string(),
+                author: None,
+                platform: None,
+                quirks: Some(Preset::SuperChip),
+                tick_rate: Some(30),
+                keys: Some("7 and 9 move".to_string()),
+            })
+        );
+        assert_eq!(db.lookup(b"").unwrap().quirks, None);
+        assert_eq!(db.lookup(b"abcd"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let hash = "[a9993e364706816aba3e25717850c26c9cd0d89d]";
+        assert!(RomDb::parse("[maze]\ntitle = \"Maze\"").is_err());
+        assert!(RomDb::parse(&format!("{hash}\nauthor = \"nobody\"")).is_err());
+        assert!(RomDb::parse(&format!("{hash}\ntitle = \"x\"\nquirks = \"vp\"")).is_err());
+        assert!(RomDb::parse(&format!("{hash}\ntitle = \"x\"\ntickrate = 9")).is_err());
+    }
+
+    /// Every rom that ships with the emulator has an entry, so rebuilding one without updating
+    /// its hash fails here
+    #[test]
+    fn test_bundled_covers_shipped_roms() {
+        let db = RomDb::bundled();
+        let roms = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("roms");
+        let tests = fs::read_dir(roms.join("tests")).unwrap();
+        let paths = tests
+            .map(|entry| entry.unwrap().path())
+            .chain([roms.join("maze.ch8")])
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"));
+        for path in paths {
+            let rom = fs::read(&path).unwrap();
+            assert!(
+                db.lookup(&rom).is_some(),
+                "{} ({}) is missing from roms/romdb.toml",
+                path.display(),
+                sha1(&rom)
+            );
+        }
+        assert_eq!(
+            db.lookup(&fs::read(roms.join("maze.ch8")).unwrap())
+                .unwrap()
+                .title,
+            "Maze"
+        );
+    }
+}
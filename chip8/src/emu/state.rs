@@ -0,0 +1,410 @@
+// Save states: the whole machine as bytes, so a session can be resumed or a bug handed over.
+//
+// Layout, all numbers little-endian:
+//   "CH8S" magic, u16 format version, u32 payload length, u32 CRC-32 of the payload,
+//   then the payload, written and read field by field in the order of `save_state`.
+//
+// Version 2 added Cxnn's random number generator after the quirks.
+use std::{fs, path::Path};
+
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+
+use super::{
+    cpu::STACK_SIZE,
+    gpu::Gpu,
+    input::{KeyWait, Keypad},
+    iset::OpCode,
+    mem::{RAM_SIZE, XO_RAM_SIZE},
+    quirks::Quirks,
+    random::SeededRandom,
+    Emulator,
+};
+
+pub const MAGIC: &[u8; 4] = b"CH8S";
+/// Bump whenever the payload layout changes, older states are then refused rather than misread
+pub const VERSION: u16 = 2;
+const HEADER_LEN: usize = 4 + 2 + 4 + 4;
+
+impl Emulator {
+    /// Serialize the registers, I, PC, stack, timers, keypad, ram, framebuffer, quirks and where
+    /// the random number generator is in its stream
+    pub fn save_state(&self) -> Vec<u8> {
+        let cpu = &self.cpu;
+        let memory = &cpu.memory;
+        let mut payload = Vec::with_capacity(memory.ram.len() + 1024);
+        payload.extend(cpu.registers);
+        payload.extend(cpu.index_register.to_le_bytes());
+        payload.extend(cpu.program_counter.to_le_bytes());
+        payload.extend(cpu.current_opcode.0.to_le_bytes());
+        for address in cpu.stack {
+            payload.extend(address.to_le_bytes());
+        }
+        payload.push(cpu.stack_pointer as u8);
+        payload.push(memory.delay_timer.get());
+        payload.push(memory.sound_timer.get());
+        payload.extend(memory.pad.bits().to_le_bytes());
+        match cpu.key_wait {
+            Some(wait) => {
+                payload.push(1);
+                payload.push(wait.register);
+                payload.extend(wait.seen_pressed.to_le_bytes());
+            }
+            None => payload.extend([0; 4]),
+        }
+        payload.push(cpu.waiting_for_vblank as u8);
+        payload.push(cpu.exited as u8);
+        payload.extend(cpu.rpl_flags);
+        payload.push(quirk_bits(&cpu.quirks));
+        // a source that can't be resumed, like a FixedSequence, is left as it is on load
+        match cpu.rng.position() {
+            Some((seed, word_pos)) => {
+                payload.push(1);
+                payload.extend(seed.to_le_bytes());
+                payload.extend(word_pos.to_le_bytes());
+            }
+            None => payload.extend([0; 1 + 8 + 16]),
+        }
+        payload.push(memory.gpu.hires as u8);
+        payload.push(memory.gpu.selected_planes);
+        payload.extend(pack_pixels(&memory.gpu.screen));
+        payload.extend(pack_pixels(&memory.gpu.second_plane));
+        payload.extend((memory.ram.len() as u32).to_le_bytes());
+        payload.extend(&memory.ram);
+
+        let mut state = Vec::with_capacity(HEADER_LEN + payload.len());
+        state.extend(MAGIC);
+        state.extend(VERSION.to_le_bytes());
+        state.extend((payload.len() as u32).to_le_bytes());
+        state.extend(crc32fast::hash(&payload).to_le_bytes());
+        state.extend(payload);
+        state
+    }
+
+    /// Restore a state from `save_state`. Nothing changes unless the whole state is valid.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<()> {
+        let mut header = Reader::new(state);
+        if header.take(4)? != MAGIC {
+            bail!("not a chip8 save state");
+        }
+        let version = header.u16()?;
+        if version != VERSION {
+            bail!("save state is format version {version}, this build reads version {VERSION}");
+        }
+        let len = header.u32()? as usize;
+        let checksum = header.u32()?;
+        let payload = header.take(len)?;
+        if crc32fast::hash(payload) != checksum {
+            bail!("save state checksum mismatch, the file is corrupt");
+        }
+
+        let mut r = Reader::new(payload);
+        let mut registers = [0; 16];
+        registers.copy_from_slice(r.take(16)?);
+        let index_register = r.u16()?;
+        let program_counter = r.u16()?;
+        let current_opcode = OpCode(r.u16()?);
+        let mut stack = [0; STACK_SIZE];
+        for address in &mut stack {
+            *address = r.u16()?;
+        }
+        let stack_pointer = r.u8()? as usize;
+        if stack_pointer > STACK_SIZE {
+            bail!("save state has a call depth of {stack_pointer}, more than {STACK_SIZE}");
+        }
+        let delay = r.u8()?;
+        let sound = r.u8()?;
+        let pad = Keypad::from_bits(r.u16()?);
+        let waiting_for_key = r.u8()? != 0;
+        let wait_register = r.u8()?;
+        if wait_register > 0xF {
+            bail!("save state waits for a key into V{wait_register:X}, there is no such register");
+        }
+        let seen_pressed = r.u16()?;
+        let key_wait = waiting_for_key.then_some(KeyWait {
+            register: wait_register,
+            seen_pressed,
+        });
+        let waiting_for_vblank = r.u8()? != 0;
+        let exited = r.u8()? != 0;
+        let mut rpl_flags = [0; 16];
+        rpl_flags.copy_from_slice(r.take(16)?);
+        let quirks = quirks_from_bits(r.u8()?);
+        let seeded = r.u8()? != 0;
+        let seed = r.u64()?;
+        let word_pos = r.u128()?;
+        let mut gpu = Gpu::new();
+        gpu.set_hires(r.u8()? != 0);
+        gpu.selected_planes = r.u8()? & 3;
+        let packed_len = gpu.screen.len().div_ceil(8);
+        unpack_pixels(r.take(packed_len)?, &mut gpu.screen);
+        unpack_pixels(r.take(packed_len)?, &mut gpu.second_plane);
+        let ram_len = r.u32()? as usize;
+        if ram_len != RAM_SIZE && ram_len != XO_RAM_SIZE {
+            bail!("save state has {ram_len} bytes of memory, expected {RAM_SIZE} or {XO_RAM_SIZE}");
+        }
+        let ram = r.take(ram_len)?.to_vec();
+        if !r.is_empty() {
+            bail!("save state has trailing bytes");
+        }
+
+        let cpu = &mut self.cpu;
+        cpu.registers = registers;
+        cpu.index_register = index_register;
+        cpu.program_counter = program_counter;
+        cpu.current_opcode = current_opcode;
+        cpu.stack = stack;
+        cpu.stack_pointer = stack_pointer;
+        cpu.key_wait = key_wait;
+        cpu.waiting_for_vblank = waiting_for_vblank;
+        cpu.exited = exited;
+        cpu.rpl_flags = rpl_flags;
+        cpu.quirks = quirks;
+        if seeded {
+            cpu.rng = Box::new(SeededRandom::resume(seed, word_pos));
+        }
+        cpu.memory.delay_timer.set(delay);
+        cpu.memory.sound_timer.set(sound);
+        cpu.memory.pad = pad;
+        cpu.memory.gpu = gpu;
+        cpu.memory.ram = ram;
+        Ok(())
+    }
+
+    /// Write `save_state` to `path`
+    pub fn save_state_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).wrap_err_with(|| format!("creating {}", dir.display()))?;
+        }
+        fs::write(path, self.save_state()).wrap_err_with(|| format!("writing {}", path.display()))
+    }
+
+    /// Read a state written by `save_state_file`
+    pub fn load_state_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let state = fs::read(path).wrap_err_with(|| format!("reading {}", path.display()))?;
+        self.load_state(&state)
+            .wrap_err_with(|| format!("loading {}", path.display()))
+    }
+}
+
+/// One bit per quirk in declaration order
+fn quirk_bits(quirks: &Quirks) -> u8 {
+    [
+        quirks.shift_uses_vy,
+        quirks.load_store_increments_i,
+        quirks.jump_uses_vx,
+        quirks.clip_sprites,
+        quirks.logic_resets_vf,
+        quirks.display_wait,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (bit, &on)| bits | (on as u8) << bit)
+}
+
+fn quirks_from_bits(bits: u8) -> Quirks {
+    let on = |bit: u8| bits & (1 << bit) != 0;
+    Quirks {
+        shift_uses_vy: on(0),
+        load_store_increments_i: on(1),
+        jump_uses_vx: on(2),
+        clip_sprites: on(3),
+        logic_resets_vf: on(4),
+        display_wait: on(5),
+    }
+}
+
+/// 8 pixels to a byte, first pixel in the lowest bit
+fn pack_pixels(pixels: &[bool]) -> Vec<u8> {
+    pixels
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (bit, &on)| byte | (on as u8) << bit)
+        })
+        .collect()
+}
+
+fn unpack_pixels(packed: &[u8], pixels: &mut [bool]) {
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = packed[i / 8] & (1 << (i % 8)) != 0;
+    }
+}
+
+/// Walks a byte slice, failing instead of panicking when it runs out
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            bail!("save state is truncated");
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn u128(&mut self) -> Result<u128> {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(self.take(16)?);
+        Ok(u128::from_le_bytes(bytes))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod statetests {
+    use super::HEADER_LEN;
+    use crate::emu::{
+        input::KeyWait, instruction::Instruction, quirks::Preset, random::FixedSequence, Emulator,
+    };
+
+    /// A machine with something in every field
+    fn busy_emulator() -> Emulator {
+        let mut emu = Emulator::new();
+        emu.set_preset(Preset::XoChip);
+        emu.load_font();
+        let cpu = &mut emu.cpu;
+        cpu.registers[3] = 0x10;
+        cpu.registers[15] = 1;
+        cpu.index_register = 0x1234;
+        cpu.program_counter = 0x2A0;
+        cpu.push_stack(0x202).unwrap();
+        cpu.push_stack(0x310).unwrap();
+        cpu.key_wait = Some(KeyWait::new(7));
+        cpu.rpl_flags[2] = 0x55;
+        cpu.memory.delay_timer.set(30);
+        cpu.memory.sound_timer.set(4);
+        cpu.memory.pad.press(0xA);
+        cpu.memory.ram[0xFFFF] = 0xEE;
+        cpu.memory.gpu.set_hires(true);
+        cpu.memory.gpu.screen[129] = true;
+        cpu.memory.gpu.second_plane[8191] = true;
+        cpu.memory.gpu.selected_planes = 3;
+        emu
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let emu = busy_emulator();
+        let state = emu.save_state();
+        assert_eq!(&state[..4], b"CH8S");
+
+        let mut restored = Emulator::new();
+        restored.load_state(&state).unwrap();
+        let (a, b) = (&emu.cpu, &restored.cpu);
+        assert_eq!(a.registers, b.registers);
+        assert_eq!(a.index_register, b.index_register);
+        assert_eq!(a.program_counter, b.program_counter);
+        assert_eq!(a.stack(), b.stack());
+        assert_eq!(a.key_wait, b.key_wait);
+        assert_eq!(a.rpl_flags, b.rpl_flags);
+        assert_eq!(a.quirks, b.quirks);
+        assert_eq!(b.memory.delay_timer.get(), 30);
+        assert_eq!(b.memory.sound_timer.get(), 4);
+        assert_eq!(a.memory.pad, b.memory.pad);
+        assert_eq!(a.memory.ram, b.memory.ram);
+        assert!(b.memory.gpu.hires);
+        assert_eq!(a.memory.gpu.screen, b.memory.gpu.screen);
+        assert_eq!(a.memory.gpu.second_plane, b.memory.gpu.second_plane);
+        assert_eq!(b.memory.gpu.selected_planes, 3);
+        // saving again gives the same bytes
+        assert_eq!(restored.save_state(), state);
+    }
+
+    #[test]
+    fn test_state_keeps_the_random_stream() {
+        let draw = |emu: &mut Emulator| -> Vec<u8> {
+            (0..8)
+                .map(|_| {
+                    let random = Instruction::Random { x: 0, nn: 0xFF };
+                    emu.cpu.execute(random).unwrap();
+                    emu.cpu.registers[0]
+                })
+                .collect()
+        };
+        let mut emu = Emulator::new();
+        emu.cpu.seed_rng(99);
+        draw(&mut emu);
+        let state = emu.save_state();
+        let expected = draw(&mut emu);
+
+        // a generator from another seed is replaced by the saved one, mid-stream
+        let mut restored = Emulator::new();
+        restored.cpu.seed_rng(1);
+        restored.load_state(&state).unwrap();
+        assert_eq!(draw(&mut restored), expected);
+        // and loading again rewinds it to the same point
+        emu.load_state(&state).unwrap();
+        assert_eq!(draw(&mut emu), expected);
+
+        // a fixed sequence has no position to save, it carries on untouched
+        let mut fixed = Emulator::new();
+        fixed.cpu.set_rng(FixedSequence::new([5]));
+        fixed.load_state(&fixed.save_state()).unwrap();
+        assert_eq!(draw(&mut fixed), [5; 8]);
+    }
+
+    #[test]
+    fn test_load_state_rejects_bad_files() {
+        let state = busy_emulator().save_state();
+        let mut emu = Emulator::new();
+
+        let mut corrupt = state.clone();
+        corrupt[HEADER_LEN + 3] ^= 0xFF;
+        let err = emu.load_state(&corrupt).unwrap_err();
+        assert!(err.to_string().contains("checksum"));
+
+        let mut newer = state.clone();
+        newer[4] = 99;
+        let err = emu.load_state(&newer).unwrap_err();
+        assert!(err.to_string().contains("version 99"));
+
+        assert!(emu.load_state(&state[..state.len() - 1]).is_err());
+        assert!(emu.load_state(b"not a state").is_err());
+        // checksummed but impossible: Fx0A would store the key past VF
+        let mut waiting = busy_emulator();
+        waiting.cpu.key_wait.as_mut().unwrap().register = 16;
+        let err = emu.load_state(&waiting.save_state()).unwrap_err();
+        assert!(err.to_string().contains("V10"));
+
+        // a failed load leaves the machine alone
+        assert_eq!(emu.cpu.memory.ram.len(), 4096);
+        assert_eq!(emu.cpu.program_counter, 0x200);
+    }
+}
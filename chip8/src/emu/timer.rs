@@ -1,7 +1,29 @@
-#[derive(Debug)]
-pub struct Timer {}
+/// One of the chip8 timer registers. Once set it counts down by one every 60Hz tick until it
+/// reaches 0, the delay timer for pacing games and the sound timer for how long to beep.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Timer {
+    value: u8,
+}
 impl Timer {
     pub(crate) fn new() -> Self {
-        Self {}
+        Self { value: 0 }
+    }
+
+    pub fn get(&self) -> u8 {
+        self.value
+    }
+
+    pub fn set(&mut self, value: u8) {
+        self.value = value;
+    }
+
+    /// Still counting down
+    pub fn is_active(&self) -> bool {
+        self.value > 0
+    }
+
+    /// Advance by one 60Hz period of emulated time
+    pub fn tick(&mut self) {
+        self.value = self.value.saturating_sub(1);
     }
 }
@@ -0,0 +1,227 @@
+// Instruction traces: one line per executed instruction, for diffing a run against another
+// emulator's log to find the first instruction where the two disagree.
+//
+//   00000002 0204 8125 SUB V1, V2           V 00 01 01 00 00 00 00 00 00 00 00 00 00 00 00 01 I 0000
+//
+// Fields are the cycle (instructions executed before this one), the pc, the opcode word at the
+// pc, the disassembly padded to 20 columns, then V0-VF and I as they are after the instruction
+// ran. Everything is upper case hex apart from the cycle. Halted cycles, waiting for a key or
+// for the vblank, execute nothing and aren't logged or counted.
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use color_eyre::{eyre::WrapErr, Result};
+
+use super::{cpu::Cpu, debugger::parse_number, disasm};
+
+/// Which instructions make it into the trace
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only instructions at these addresses, every address when empty
+    pub addresses: Vec<RangeInclusive<u16>>,
+    /// First cycle to log
+    pub start: u64,
+    /// Cycle to stop logging at, the run carries on untraced. `None` logs to the end.
+    pub stop: Option<u64>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, cycle: u64, pc: u16) -> bool {
+        cycle >= self.start
+            && self.stop.is_none_or(|stop| cycle < stop)
+            && (self.addresses.is_empty() || self.addresses.iter().any(|r| r.contains(&pc)))
+    }
+
+    /// Reads an address range like `0x200-0x2FF`, or a single address
+    pub fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+        let (start, end) = text.split_once('-').unwrap_or((text, text));
+        let (start, end) = (parse_number(start)?, parse_number(end)?);
+        if start > end {
+            return Err(format!("trace range `{text}` ends before it starts"));
+        }
+        Ok(start..=end)
+    }
+}
+
+/// An instruction that has been fetched but not yet run
+#[derive(Debug)]
+pub(crate) struct Traced {
+    cycle: u64,
+    pc: u16,
+    opcode: u16,
+    text: String,
+}
+
+/// Writes the trace of a run, driven by `Emulator::run_cycles`
+pub struct Tracer {
+    out: Box<dyn Write>,
+    filter: TraceFilter,
+    cycle: u64,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("filter", &self.filter)
+            .field("cycle", &self.cycle)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    pub fn new(out: impl Write + 'static, filter: TraceFilter) -> Self {
+        Self {
+            out: Box::new(out),
+            filter,
+            cycle: 0,
+        }
+    }
+
+    /// Trace to the file at `path`, replacing it
+    pub fn create(path: impl AsRef<Path>, filter: TraceFilter) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).wrap_err_with(|| format!("creating {}", path.display()))?;
+        Ok(Self::new(BufWriter::new(file), filter))
+    }
+
+    /// Instructions executed since tracing began
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Flush whatever is buffered once the emulator stops
+    pub fn finish(&mut self) -> Result<()> {
+        self.out.flush().wrap_err("writing the trace")
+    }
+
+    /// Called before each cpu step. Counts the instruction about to run and, if the filter wants
+    /// it, reads it before it can overwrite itself.
+    pub(crate) fn before_step(&mut self, cpu: &Cpu) -> Option<Traced> {
+        if cpu.waiting_for_vblank || cpu.exited || cpu.key_wait.is_some() {
+            return None;
+        }
+        let (cycle, pc) = (self.cycle, cpu.program_counter);
+        self.cycle += 1;
+        if !self.filter.matches(cycle, pc) {
+            return None;
+        }
+        let ram = &cpu.memory.ram;
+        let opcode = match ram.get(pc as usize..pc as usize + 2) {
+            Some(&[high, low]) => u16::from_be_bytes([high, low]),
+            _ => 0,
+        };
+        let (text, _) = disasm::instruction_at(ram, pc);
+        Some(Traced {
+            cycle,
+            pc,
+            opcode,
+            text,
+        })
+    }
+
+    /// Log `traced` with the registers it left behind
+    pub(crate) fn after_step(&mut self, cpu: &Cpu, traced: Traced) -> io::Result<()> {
+        let Traced {
+            cycle,
+            pc,
+            opcode,
+            text,
+        } = traced;
+        write!(self.out, "{cycle:08} {pc:04X} {opcode:04X} {text:<20} V")?;
+        for register in cpu.registers {
+            write!(self.out, " {register:02X}")?;
+        }
+        writeln!(self.out, " I {:04X}", cpu.index_register)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tracetests {
+    use super::{TraceFilter, Tracer};
+    use crate::emu::Emulator;
+    use std::{
+        cell::RefCell,
+        io::{self, Write},
+        rc::Rc,
+    };
+
+    /// A Write the test can read back after handing it to the tracer
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Counts V1 down from 2 with 8xy5, so the borrow shows up in VF
+    fn emulator(filter: TraceFilter) -> (Emulator, Shared) {
+        let mut emu = Emulator::new();
+        let rom = [
+            0x61, 0x02, // LD V1, 0x02
+            0x62, 0x01, // LD V2, 0x01
+            0x81, 0x25, // SUB V1, V2
+            0x12, 0x04, // JP 0x204
+        ];
+        emu.cpu.memory.ram[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        let out = Shared::default();
+        emu.tracer = Some(Tracer::new(out.clone(), filter));
+        (emu, out)
+    }
+
+    fn lines(out: &Shared) -> Vec<String> {
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_trace_format() {
+        let (mut emu, out) = emulator(TraceFilter::default());
+        emu.run_cycles(7).unwrap();
+        assert_eq!(emu.tracer.as_ref().unwrap().cycle(), 7);
+        let lines = lines(&out);
+        assert_eq!(lines.len(), 7);
+        assert_eq!(
+            lines[0],
+            "00000000 0200 6102 LD V1, 0x02          V 00 02 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000"
+        );
+        assert_eq!(
+            lines[2],
+            "00000002 0204 8125 SUB V1, V2           V 00 01 01 00 00 00 00 00 00 00 00 00 00 00 00 01 I 0000"
+        );
+        // 0 - 1 borrows, VF goes to 0
+        assert_eq!(
+            lines[6],
+            "00000006 0204 8125 SUB V1, V2           V 00 FF 01 00 00 00 00 00 00 00 00 00 00 00 00 00 I 0000"
+        );
+    }
+
+    #[test]
+    fn test_trace_filters() {
+        let filter = TraceFilter {
+            addresses: vec![TraceFilter::parse_range("0x204").unwrap()],
+            start: 3,
+            stop: Some(7),
+        };
+        let (mut emu, out) = emulator(filter);
+        emu.run_cycles(20).unwrap();
+        let lines = lines(&out);
+        let cycles: Vec<&str> = lines.iter().map(|line| &line[..8]).collect();
+        assert_eq!(cycles, ["00000004", "00000006"]);
+
+        assert_eq!(TraceFilter::parse_range("0x200-0x2FF"), Ok(0x200..=0x2FF));
+        assert!(TraceFilter::parse_range("0x2FF-0x200").is_err());
+        assert!(TraceFilter::parse_range("start").is_err());
+    }
+}
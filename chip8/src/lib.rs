@@ -0,0 +1,3 @@
+// Copyright (c) 2024 galus. All Rights Reserved.
+//! CHIP-8 emulator core, shared by the `chip8` binary and its tests.
+pub mod emu;
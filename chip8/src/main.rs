@@ -5,59 +5,499 @@
 // / / /_  _\      | (_| | (_| | | |_| \__ \            /_  _\/ /
 ///_/    \/         \__, |\__,_|_|\__,_|___/              \/ /_/
 //                  |___/
-use color_eyre::Result;
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Result, Section,
+};
 
 mod emojis;
-mod emu;
 
+use chip8::emu::{
+    analysis::{analyze, Analysis, Platform},
+    asm,
+    audio::WavSink,
+    debugger::parse_number,
+    disasm,
+    error::Chip8Error,
+    frontend::{
+        headless::{HeadlessFrontend, ImageFormat},
+        tui::TuiFrontend,
+        Frontend,
+    },
+    mem::{PROGRAM_START, XO_RAM_SIZE},
+    movie::{Movie, MoviePlayer, MovieRecorder},
+    quirks::Preset,
+    rewind::{Rewind, DEFAULT_INTERVAL},
+    romdb::{self, RomDb, RomInfo},
+    trace::{TraceFilter, Tracer},
+    Emulator,
+};
 use emojis::EMOJIS; // Avoid Emoji Nightmares
-use emu::Emulator;
+use std::{
+    fs,
+    io::{self, Read},
+    time::Duration,
+};
+
+/// Instructions executed between two frames, ~540Hz at 60fps
+const CYCLES_PER_FRAME: usize = 9;
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+const USAGE: &str = "usage: chip8 [options] <rom.ch8 | ->";
+
+/// Flags followed by a value, so the rom path can be told apart from the values
+const VALUE_FLAGS: [&str; 21] = [
+    "--quirks",
+    "--tick-rate",
+    "--romdb",
+    "--start",
+    "--wav",
+    "--seed",
+    "--rewind-kib",
+    "--frames",
+    "--dump-dir",
+    "--dump-at",
+    "--format",
+    "--record",
+    "--replay",
+    "--break",
+    "--watch",
+    "--watch-reg",
+    "--break-if",
+    "--trace",
+    "--trace-from",
+    "--trace-to",
+    "--trace-range",
+];
 
 fn main() -> Result<()> {
     color_eyre::install()?; // error hooks
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("disasm") => return disassemble(args.get(2)),
+        Some("asm") => return assemble(args.get(2), args.get(3)),
+        Some("info") => return info(args.get(2)),
+        _ => {}
+    }
+
+    let Some(rom_path) = rom_argument(&args) else {
+        bail!("{USAGE}");
+    };
+
     println!("{} Initializing emulator", EMOJIS[0]);
     let mut emu: Emulator = Emulator::new();
+    if let Some(wav_path) = flag_value(&args, "--wav") {
+        emu.audio = Box::new(WavSink::create(wav_path)?);
+    }
+    if let Some(seed) = flag_value(&args, "--seed") {
+        let seed: u64 = seed
+            .parse()
+            .map_err(|_| eyre!("--seed expects a number, got `{seed}`"))?;
+        emu.cpu.seed_rng(seed);
+    }
+    if let Some(kib) = flag_value(&args, "--rewind-kib") {
+        let bytes = kib
+            .parse::<usize>()
+            .ok()
+            .and_then(|kib| kib.checked_mul(1024))
+            .ok_or_else(|| eyre!("--rewind-kib expects a size in KiB, got `{kib}`"))?;
+        emu.rewind = Rewind::new(DEFAULT_INTERVAL, bytes);
+    }
+
+    add_watches(&mut emu, &args)?;
+    if let Some(path) = flag_value(&args, "--trace") {
+        emu.tracer = Some(Tracer::create(path, trace_filter(&args)?)?);
+    }
 
     println!("\t{} Loading fonts into emulator...", EMOJIS[1]);
-    let _ = emu.load_font();
+    emu.load_font();
 
-    let rom_path: &str = "./roms/maze.ch8";
     println!("\t{} Reading rom {}...", EMOJIS[2], rom_path);
-    let rom_data = std::fs::read(rom_path)?;
-    emu.cpu.memory.rom = rom_data;
+    let rom_data = read_rom(rom_path)?;
+    let info = look_up(&rom_data, &args)?;
+    let (preset, cycles_per_frame) = apply_settings(&mut emu, info.as_ref(), &args)?;
 
     println!("\t{} Loading rom into emulator...", EMOJIS[3]);
-    let _ = emu.load_rom(); // clears emu.rom_buffer
+    load_rom(&mut emu, &rom_data, &args)?;
+    warn_about(&analyze(&rom_data, emu.cpu.program_counter), preset);
+
+    if args.iter().any(|arg| arg == "--headless") {
+        return run_headless(&mut emu, cycles_per_frame, &args);
+    }
 
     println!("\t{} Initializing terminal...", EMOJIS[4]);
-    let mut terminal = emu.cpu.memory.gpu.init()?;
+    let mut frontend = TuiFrontend::init()?;
+    if let Some(info) = &info {
+        frontend = frontend.with_rom(info);
+    }
 
     println!("\t{} Running app...", EMOJIS[5]);
+    let result = run_on(&mut emu, &mut frontend, cycles_per_frame, &args);
+
+    if let Err(err) = frontend.restore() {
+        eprintln!(
+            "failed to restore terminal. Run `reset` or restart your terminal to recover: {}",
+            err
+        );
+    }
+
+    // report after leaving the alternate screen, otherwise the message is wiped with it
+    result
+        .and_then(|_| Ok(emu.audio.finish()?))
+        .and_then(|_| finish_trace(&mut emu))
+        .wrap_err("failed to process")?;
+
+    println!("{} Exiting...", EMOJIS[6]);
+    Ok(())
+}
 
-    loop {
-        let _ = emu.cpu.fetch_opcode();
-        if let Err(err) = emu.cpu.process() {
-            eprintln!("failed to process.: {}", err);
-            break;
+/// `--headless --frames N [--dump-dir out/] [--dump-at 10,20] [--format pbm|png]`:
+/// run N frames with no terminal, saving the chosen frames or only the last one
+fn run_headless(emu: &mut Emulator, cycles_per_frame: usize, args: &[String]) -> Result<()> {
+    let frames: u64 = match flag_value(args, "--frames") {
+        Some(frames) => frames
+            .parse()
+            .map_err(|_| eyre!("--frames expects a number of frames, got `{frames}`"))?,
+        // a replay ends with its movie
+        None if flag_value(args, "--replay").is_some() => u64::MAX,
+        None => bail!("usage: chip8 <rom> --headless --frames N [--dump-dir out/] [--dump-at 10,20] [--format pbm|png]"),
+    };
+    let mut frontend = HeadlessFrontend::new(frames);
+    if let Some(dir) = flag_value(args, "--dump-dir") {
+        let format: ImageFormat = flag_value(args, "--format")
+            .unwrap_or("pbm")
+            .parse()
+            .map_err(|err: String| eyre!(err))?;
+        let at = match flag_value(args, "--dump-at") {
+            Some(list) => list
+                .split(',')
+                .map(|frame| frame.trim().parse::<u64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| eyre!("--dump-at expects frame numbers like 10,20, got `{list}`"))?,
+            None => Vec::new(),
+        };
+        if let Some(&frame) = at.iter().find(|&&frame| frame == 0 || frame > frames) {
+            bail!("--dump-at {frame} is outside the run, frames are numbered 1 to {frames}");
         }
+        frontend = frontend.dump_to(dir, format, &at);
+    }
 
-        emu.cpu.memory.gpu.run(&mut terminal)?;
+    println!("\t{} Running headless...", EMOJIS[5]);
+    run_on(emu, &mut frontend, cycles_per_frame, args)?;
+    frontend.finish(emu)?;
+    emu.audio.finish()?;
+    finish_trace(emu)?;
+    let missed = frontend.missed();
+    if !missed.is_empty() {
+        let missed: Vec<String> = missed.iter().map(u64::to_string).collect();
+        bail!(
+            "the rom stopped at frame {}, --dump-at {} was never reached",
+            frontend.presented(),
+            missed.join(", ")
+        );
+    }
+    println!("{} Ran {} frames", EMOJIS[6], frontend.presented());
+    Ok(())
+}
 
-        // Trying to figure out how to have above return a fn ptr
-        // display
-        // input
+/// Run on `frontend`, recording the input to `--record movie.txt` or replaying `--replay movie.txt`
+fn run_on(
+    emu: &mut Emulator,
+    frontend: &mut dyn Frontend,
+    cycles_per_frame: usize,
+    args: &[String],
+) -> Result<()> {
+    if let Some(path) = flag_value(args, "--replay") {
+        let movie = Movie::load(path)?;
+        let mut player = MoviePlayer::new(frontend, movie, emu);
+        return Ok(emu.run(&mut player, cycles_per_frame, FRAME_DURATION)?);
+    }
+    if let Some(path) = flag_value(args, "--record") {
+        let mut recorder = MovieRecorder::new(frontend, emu)?;
+        let result = emu.run(&mut recorder, cycles_per_frame, FRAME_DURATION);
+        // keep the movie even when the run failed, that is when it's wanted most
+        recorder.movie().save(path)?;
+        return Ok(result?);
+    }
+    Ok(emu.run(frontend, cycles_per_frame, FRAME_DURATION)?)
+}
 
-        // add a conditioal break
-        break;
+/// The first argument that isn't a flag or a flag's value
+fn rom_argument(args: &[String]) -> Option<&str> {
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if VALUE_FLAGS.contains(&arg.as_str()) {
+            rest.next();
+        } else if arg == "-" || !arg.starts_with('-') {
+            return Some(arg);
+        }
     }
+    None
+}
 
-    if let Err(err) = emu.cpu.memory.gpu.restore() {
-        eprintln!(
-            "failed to restore terminal. Run `reset` or restart your terminal to recover: {}",
-            err
+/// The rom at `path`, or stdin for `-`
+fn read_rom(path: &str) -> Result<Vec<u8>> {
+    let rom = if path == "-" {
+        let mut rom = Vec::new();
+        io::stdin()
+            .read_to_end(&mut rom)
+            .wrap_err("can't read the rom from stdin")?;
+        rom
+    } else {
+        match fs::read(path) {
+            Ok(rom) => rom,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(eyre!("there is no rom at `{path}`"))
+                    .suggestion("check the path, or pass - to read the rom from stdin");
+            }
+            Err(err) => return Err(err).wrap_err_with(|| format!("can't read rom `{path}`")),
+        }
+    };
+    if rom.is_empty() {
+        bail!("rom `{path}` is empty");
+    }
+    Ok(rom)
+}
+
+/// The rom's entry in the bundled rom database, or in `--romdb my-roms.toml` which adds to it
+fn look_up(rom: &[u8], args: &[String]) -> Result<Option<RomInfo>> {
+    let mut db = RomDb::bundled();
+    if let Some(path) = flag_value(args, "--romdb") {
+        db.extend_from(path)?;
+    }
+    let Some(info) = db.lookup(rom) else {
+        return Ok(None);
+    };
+    let mut found = info.title.clone();
+    if let Some(author) = &info.author {
+        found += &format!(" by {author}");
+    }
+    if let Some(platform) = &info.platform {
+        found += &format!(" for {platform}");
+    }
+    println!("\t{} Found {found} in the rom database", EMOJIS[2]);
+    if let Some(keys) = &info.keys {
+        println!("\t{} Keys: {keys}", EMOJIS[3]);
+    }
+    Ok(Some(info.clone()))
+}
+
+/// Pick the quirks and the instructions per frame: `--quirks` and `--tick-rate` if given,
+/// otherwise what the rom database recommends, otherwise the defaults
+fn apply_settings(
+    emu: &mut Emulator,
+    info: Option<&RomInfo>,
+    args: &[String],
+) -> Result<(Preset, usize)> {
+    let preset = match flag_value(args, "--quirks") {
+        Some(name) => name.parse::<Preset>().map_err(|err| eyre!(err))?,
+        None => info
+            .and_then(|info| info.quirks)
+            .unwrap_or(Preset::CosmacVip),
+    };
+    emu.set_preset(preset);
+    let cycles_per_frame = match flag_value(args, "--tick-rate") {
+        Some(rate) => rate
+            .parse()
+            .ok()
+            .filter(|&rate| rate > 0)
+            .ok_or_else(|| eyre!("--tick-rate expects instructions per frame, got `{rate}`"))?,
+        None => info
+            .and_then(|info| info.tick_rate)
+            .unwrap_or(CYCLES_PER_FRAME),
+    };
+    Ok((preset, cycles_per_frame))
+}
+
+/// Point out a rom that needs a newer platform than `preset`, and the instructions in it the
+/// emulator can't run
+fn warn_about(analysis: &Analysis, preset: Preset) {
+    if analysis.platform > Platform::of(preset) {
+        let platform = analysis.platform;
+        let why = analysis.platform_reason.as_deref().unwrap_or_default();
+        println!(
+            "\t{} This looks like a rom for {platform} ({why}), try --quirks {}",
+            EMOJIS[7],
+            platform.preset()
+        );
+    }
+    const SHOWN: usize = 5;
+    for unsupported in analysis.unsupported.iter().take(SHOWN) {
+        println!(
+            "\t{} {:04X} at {:#05X}: {}",
+            EMOJIS[7], unsupported.opcode, unsupported.address, unsupported.reason
         );
     }
+    if analysis.unsupported.len() > SHOWN {
+        println!(
+            "\t{} ...and {} more, `chip8 info` lists them all",
+            EMOJIS[7],
+            analysis.unsupported.len() - SHOWN
+        );
+    }
+}
 
-    println!("{} Exiting...", EMOJIS[6]);
+/// Load at 0x200, or wherever `--start` says, e.g. `--start 0x600` for ETI 660 programs.
+/// Sizes are checked against the memory of the `--quirks` platform.
+fn load_rom(emu: &mut Emulator, rom: &[u8], args: &[String]) -> Result<()> {
+    let start = match flag_value(args, "--start") {
+        Some(start) => parse_number(start)
+            .map_err(|_| eyre!("--start expects an address like 0x600, got `{start}`"))?,
+        None => PROGRAM_START,
+    };
+    if start < PROGRAM_START {
+        bail!("--start {start:#05X} would overwrite the fonts, programs start at 0x200 or later");
+    }
+    match emu.load_rom(rom, start) {
+        Err(err @ Chip8Error::RomTooLarge { .. }) if emu.cpu.memory.ram.len() < XO_RAM_SIZE => {
+            Err(eyre!(err)).suggestion("XO-CHIP roms can fill 64KiB, try --quirks xochip")
+        }
+        result => Ok(result?),
+    }
+}
+
+/// `--break 0x2A0`, `--watch 0x300-0x30F:w`, `--watch-reg V3` and `--break-if "V3 == 0x10"`,
+/// each can be given more than once
+fn add_watches(emu: &mut Emulator, args: &[String]) -> Result<()> {
+    for address in flag_values(args, "--break") {
+        let address = parse_number(address)
+            .map_err(|_| eyre!("--break expects an address like 0x2A0, got `{address}`"))?;
+        emu.debugger.add_breakpoint(address);
+    }
+    for watch in flag_values(args, "--watch") {
+        let watch = watch.parse().map_err(|err| eyre!("--watch: {err}"))?;
+        emu.debugger.watch_memory(watch);
+    }
+    for register in flag_values(args, "--watch-reg") {
+        let register = register
+            .parse()
+            .map_err(|err| eyre!("--watch-reg: {err}"))?;
+        emu.debugger.watch_register(register);
+    }
+    for condition in flag_values(args, "--break-if") {
+        let condition = condition
+            .parse()
+            .map_err(|err| eyre!("--break-if: {err}"))?;
+        emu.debugger.break_if(condition);
+    }
+    Ok(())
+}
+
+/// `--trace-from 1000 --trace-to 2000 --trace-range 0x200-0x2FF`, the range can repeat
+fn trace_filter(args: &[String]) -> Result<TraceFilter> {
+    let cycle = |flag: &str| -> Result<Option<u64>> {
+        flag_value(args, flag)
+            .map(|cycle| {
+                cycle
+                    .parse()
+                    .map_err(|_| eyre!("{flag} expects a cycle number, got `{cycle}`"))
+            })
+            .transpose()
+    };
+    Ok(TraceFilter {
+        addresses: flag_values(args, "--trace-range")
+            .map(|range| {
+                TraceFilter::parse_range(range).map_err(|err| eyre!("--trace-range: {err}"))
+            })
+            .collect::<Result<_>>()?,
+        start: cycle("--trace-from")?.unwrap_or(0),
+        stop: cycle("--trace-to")?,
+    })
+}
+
+fn finish_trace(emu: &mut Emulator) -> Result<()> {
+    match emu.tracer.as_mut() {
+        Some(tracer) => tracer.finish(),
+        None => Ok(()),
+    }
+}
+
+/// The argument following `flag`, e.g. `vip` for `--quirks vip`
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let position = args.iter().position(|arg| arg == flag)?;
+    args.get(position + 1).map(String::as_str)
+}
+
+/// The argument following every `flag`, for flags that can repeat
+fn flag_values<'a>(args: &'a [String], flag: &'a str) -> impl Iterator<Item = &'a str> {
+    args.windows(2)
+        .filter(move |pair| pair[0] == flag)
+        .map(|pair| pair[1].as_str())
+}
+
+/// `chip8 disasm <rom>`: print the rom as assembly instead of running it
+fn disassemble(rom_path: Option<&String>) -> Result<()> {
+    let Some(rom_path) = rom_path else {
+        bail!("usage: chip8 disasm <rom>");
+    };
+    let rom_data = read_rom(rom_path)?;
+    println!("; {} ({} bytes)", rom_path, rom_data.len());
+    for line in disasm::disassemble(&rom_data, disasm::DEFAULT_ORIGIN) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// `chip8 info <rom>`: what the rom database and a scan of the rom's code say about it
+fn info(rom_path: Option<&String>) -> Result<()> {
+    let Some(rom_path) = rom_path else {
+        bail!("usage: chip8 info <rom>");
+    };
+    let rom_data = read_rom(rom_path)?;
+    let analysis = analyze(&rom_data, PROGRAM_START);
+    println!("{rom_path}");
+    println!(
+        "  size         {} bytes, {} of them reachable code",
+        analysis.size, analysis.code_size
+    );
+    println!("  sha1         {}", romdb::sha1(&rom_data));
+    if let Some(info) = RomDb::bundled().lookup(&rom_data) {
+        println!("  title        {}", info.title);
+        if let Some(author) = &info.author {
+            println!("  author       {author}");
+        }
+    }
+    println!(
+        "  platform     {} (--quirks {})",
+        analysis.platform,
+        analysis.platform.preset()
+    );
+    if let Some(reason) = &analysis.platform_reason {
+        println!("               because of {reason}");
+    }
+    for (quirk, addresses) in &analysis.quirks {
+        let addresses: Vec<String> = addresses.iter().map(|a| format!("{a:#05X}")).collect();
+        println!("  quirk        {quirk} at {}", addresses.join(", "));
+    }
+    for unsupported in &analysis.unsupported {
+        println!(
+            "  unsupported  {:04X} at {:#05X}: {}",
+            unsupported.opcode, unsupported.address, unsupported.reason
+        );
+    }
+    println!("  instructions");
+    for (pattern, count) in analysis.most_used() {
+        println!("    {pattern} {count:>5}");
+    }
+    Ok(())
+}
+
+/// `chip8 asm <source> <out.ch8>`: build a rom from assembly source
+fn assemble(source_path: Option<&String>, rom_path: Option<&String>) -> Result<()> {
+    let (Some(source_path), Some(rom_path)) = (source_path, rom_path) else {
+        bail!("usage: chip8 asm <source> <out.ch8>");
+    };
+    let source = std::fs::read_to_string(source_path)?;
+    let rom_data = match asm::assemble(&source, disasm::DEFAULT_ORIGIN) {
+        Ok(rom_data) => rom_data,
+        Err(err) => bail!("{}:{}", source_path, err),
+    };
+    std::fs::write(rom_path, &rom_data)?;
+    println!(
+        "{} Wrote {} bytes to {}",
+        EMOJIS[1],
+        rom_data.len(),
+        rom_path
+    );
     Ok(())
 }
@@ -0,0 +1,178 @@
+// Runs the test roms in roms/tests headless and compares the screen they leave with the golden
+// bitmap next to them. The roms draw a tick or a cross per check, so a failure shows up as a
+// changed golden, and the ascii art in the failure message says which cell changed.
+//
+// After a deliberate change to what a rom shows, rewrite the goldens with
+//   UPDATE_GOLDEN=1 cargo test --test conformance
+// and look over the new .pbm files before committing them.
+//
+// Those goldens come from this emulator, so they only catch regressions. Third-party suites in
+// roms/tests/vendor are compared with the screens their authors document instead, and
+// UPDATE_GOLDEN never touches them.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chip8::emu::{
+    asm, disasm::DEFAULT_ORIGIN, frontend::headless::write_pbm, mem::PROGRAM_START, quirks::Preset,
+    Emulator,
+};
+
+/// Same speed as the binary, ~540Hz at 60fps
+const CYCLES_PER_FRAME: usize = 9;
+
+struct Case {
+    rom: &'static str,
+    preset: Preset,
+    frames: u64,
+    /// `(frame, key, pressed)`, applied before the frame runs
+    keys: &'static [(u64, u8, bool)],
+}
+
+impl Case {
+    const fn new(rom: &'static str, preset: Preset) -> Self {
+        Self {
+            rom,
+            preset,
+            frames: 120,
+            keys: &[],
+        }
+    }
+
+    fn golden(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}-{}.pbm", self.rom, self.preset))
+    }
+}
+
+const CASES: &[Case] = &[
+    Case::new("opcodes", Preset::CosmacVip),
+    Case::new("flags", Preset::CosmacVip),
+    Case::new("quirks", Preset::CosmacVip),
+    Case::new("quirks", Preset::SuperChip),
+    Case::new("quirks", Preset::XoChip),
+    Case {
+        keys: &[
+            (10, 0xA, true),
+            (15, 0xA, false),
+            (30, 5, true),
+            (45, 5, false),
+        ],
+        ..Case::new("keypad", Preset::CosmacVip)
+    },
+];
+
+/// Third-party roms from roms/tests/vendor, see the README there. Each golden is drawn from the
+/// expected screen in the suite's own documentation, never from a run of this emulator.
+const VENDORED: &[Case] = &[];
+
+fn tests_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("roms/tests")
+}
+
+fn vendor_dir() -> PathBuf {
+    tests_dir().join("vendor")
+}
+
+/// The screen `case`, a rom in `dir`, leaves behind, as a PBM
+fn run(dir: &Path, case: &Case) -> String {
+    let rom = fs::read(dir.join(format!("{}.ch8", case.rom))).unwrap();
+    let mut emu = Emulator::new();
+    emu.set_preset(case.preset);
+    emu.load_font();
+    emu.load_rom(&rom, PROGRAM_START).unwrap();
+    for frame in 0..case.frames {
+        for &(at, key, pressed) in case.keys {
+            if at == frame {
+                match pressed {
+                    true => emu.cpu.memory.pad.press(key),
+                    false => emu.cpu.memory.pad.release(key),
+                }
+            }
+        }
+        emu.run_frame(CYCLES_PER_FRAME)
+            .unwrap_or_else(|err| panic!("{} failed on frame {frame}: {err:#}", case.rom));
+    }
+    let mut pbm = Vec::new();
+    write_pbm(&emu.cpu.memory.gpu, &mut pbm).unwrap();
+    String::from_utf8(pbm).unwrap()
+}
+
+/// PBM rows as `#` and `.`, easier to read in a test failure
+fn art(pbm: &str) -> String {
+    pbm.lines()
+        .skip(2)
+        .map(|row| row.replace('1', "#").replace('0', ".") + "\n")
+        .collect()
+}
+
+#[test]
+fn test_roms_match_goldens() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failed = Vec::new();
+    for case in CASES {
+        let screen = run(&tests_dir(), case);
+        let golden = case.golden(&tests_dir());
+        if update {
+            fs::write(&golden, &screen).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_else(|err| {
+            panic!(
+                "can't read {}, create it with UPDATE_GOLDEN=1: {err}",
+                golden.display()
+            )
+        });
+        if screen != expected {
+            eprintln!(
+                "{} under {} differs from {}\nexpected:\n{}got:\n{}",
+                case.rom,
+                case.preset,
+                golden.display(),
+                art(&expected),
+                art(&screen)
+            );
+            failed.push(format!("{}-{}", case.rom, case.preset));
+        }
+    }
+    assert!(failed.is_empty(), "screens changed: {}", failed.join(", "));
+}
+
+#[test]
+fn test_vendored_roms_match_documented_screens() {
+    let mut failed = Vec::new();
+    for case in VENDORED {
+        let screen = run(&vendor_dir(), case);
+        let golden = case.golden(&vendor_dir());
+        let expected = fs::read_to_string(&golden)
+            .unwrap_or_else(|err| panic!("can't read {}: {err}", golden.display()));
+        if screen != expected {
+            eprintln!(
+                "{} under {} doesn't show the documented screen\nexpected:\n{}got:\n{}",
+                case.rom,
+                case.preset,
+                art(&expected),
+                art(&screen)
+            );
+            failed.push(format!("{}-{}", case.rom, case.preset));
+        }
+    }
+    assert!(failed.is_empty(), "wrong screens: {}", failed.join(", "));
+}
+
+/// The checked-in roms are built from the sources beside them
+#[test]
+fn test_roms_match_sources() {
+    let mut roms: Vec<&str> = CASES.iter().map(|case| case.rom).collect();
+    roms.dedup();
+    for rom in roms {
+        let source = fs::read_to_string(tests_dir().join(format!("{rom}.asm"))).unwrap();
+        let built =
+            asm::assemble(&source, DEFAULT_ORIGIN).unwrap_or_else(|err| panic!("{rom}.asm:{err}"));
+        let checked_in = fs::read(tests_dir().join(format!("{rom}.ch8"))).unwrap();
+        assert!(
+            built == checked_in,
+            "{rom}.ch8 is out of date, rebuild it from {rom}.asm"
+        );
+    }
+}